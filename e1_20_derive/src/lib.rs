@@ -0,0 +1,287 @@
+//! #[derive(RdmParameter)] for e1_20 parameter data structs
+//! Generates PdField and PdCodec from the field list, so a PID's parameter data is declared
+//! rather than hand-packed.  Fields are encoded big-endian in declaration order:
+//!
+//! - integers, bool, Uid and any other PdField, including nested derived structs
+//! - String with #[rdm(len = N)]: fixed width, padded with nulls
+//! - String, optionally with #[rdm(max_len = N)]: the rest of the parameter data
+//! - Vec<T> of a PdField, optionally with #[rdm(max_len = N)] entries: a repeated group that
+//!   runs to the end of the parameter data
+//!
+//! A value longer than its len or max_len is refused with FieldTooLong rather than cut short.
+//!
+//! The last two take everything that is left, so they can only be the last field, and so can
+//! a nested struct that ends in one.
+//!
+//! #[rdm(pid = DEVICE_LABEL)] on the struct also implements ParameterData with the struct as
+//! the GET response and SET request.  Add get or set to support only one of them, and
+//! get_request, get_response, set_request or set_response = Type where a PID differs, e.g.
+//! #[rdm(pid = SENSOR_VALUE, get_request = u8, set_request = u8, set_response = Self)].
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::{format_ident, quote};
+use syn::{parse_macro_input, Data, DeriveInput, Expr, Fields, LitInt, Member, Type};
+
+#[proc_macro_derive(RdmParameter, attributes(rdm))]
+pub fn derive_rdm_parameter(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+
+    expand(&input)
+        .unwrap_or_else(syn::Error::into_compile_error)
+        .into()
+}
+
+/// How one field goes on the wire
+enum Encoding {
+    /// A PdField
+    Field,
+    /// A string of exactly len bytes
+    FixedString { len: usize },
+    /// A string running to the end of the parameter data
+    String { max_len: Option<usize> },
+    /// PdFields repeated to the end of the parameter data
+    Repeated { max_len: Option<usize> }
+}
+
+impl Encoding {
+    fn is_trailing(&self) -> bool {
+        matches!(self, Encoding::String { .. } | Encoding::Repeated { .. })
+    }
+}
+
+/// #[rdm(..)] on the struct
+#[derive(Default)]
+struct ContainerAttrs {
+    pid: Option<Expr>,
+    get: bool,
+    set: bool,
+    get_request: Option<Type>,
+    get_response: Option<Type>,
+    set_request: Option<Type>,
+    set_response: Option<Type>
+}
+
+fn container_attrs(input: &DeriveInput) -> syn::Result<ContainerAttrs> {
+    let mut attrs = ContainerAttrs::default();
+
+    for attr in input.attrs.iter().filter(|attr| attr.path().is_ident("rdm")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("pid") {
+                attrs.pid = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("get") {
+                attrs.get = true;
+            } else if meta.path.is_ident("set") {
+                attrs.set = true;
+            } else if meta.path.is_ident("get_request") {
+                attrs.get_request = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("get_response") {
+                attrs.get_response = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("set_request") {
+                attrs.set_request = Some(meta.value()?.parse()?);
+            } else if meta.path.is_ident("set_response") {
+                attrs.set_response = Some(meta.value()?.parse()?);
+            } else {
+                return Err(meta.error("expected pid = .., get, set or get_request, get_response, set_request, set_response = .."));
+            }
+            Ok(())
+        })?;
+    }
+
+    let overrides = [&attrs.get_request, &attrs.get_response, &attrs.set_request, &attrs.set_response];
+
+    if attrs.pid.is_none() && (attrs.get || attrs.set || overrides.iter().any(|ty| ty.is_some())) {
+        return Err(syn::Error::new_spanned(&input.ident, "get, set and the request and response types need #[rdm(pid = ..)]"));
+    }
+
+    Ok(attrs)
+}
+
+/// Last path segment of ty, e.g. Vec for std::vec::Vec<Uid>
+fn type_name(ty: &Type) -> Option<String> {
+    match ty {
+        Type::Path(path) => path.path.segments.last().map(|segment| segment.ident.to_string()),
+        _ => None
+    }
+}
+
+fn field_encoding(field: &syn::Field) -> syn::Result<Encoding> {
+    let mut len : Option<usize> = None;
+    let mut max_len : Option<usize> = None;
+
+    for attr in field.attrs.iter().filter(|attr| attr.path().is_ident("rdm")) {
+        attr.parse_nested_meta(|meta| {
+            if meta.path.is_ident("len") {
+                len = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+            } else if meta.path.is_ident("max_len") {
+                max_len = Some(meta.value()?.parse::<LitInt>()?.base10_parse()?);
+            } else {
+                return Err(meta.error("expected len = .. or max_len = .."));
+            }
+            Ok(())
+        })?;
+    }
+
+    let encoding = match (type_name(&field.ty).as_deref(), len, max_len) {
+        (Some("String"), Some(_), Some(_)) => return Err(syn::Error::new_spanned(field, "a string has either len or max_len")),
+        (Some("String"), Some(len), None) => Encoding::FixedString { len },
+        (Some("String"), None, max_len) => Encoding::String { max_len },
+        (Some("Vec"), None, max_len) => Encoding::Repeated { max_len },
+        (_, None, None) => Encoding::Field,
+        (_, _, _) => return Err(syn::Error::new_spanned(field, "len applies to String, max_len to String and Vec"))
+    };
+
+    Ok(encoding)
+}
+
+fn option_tokens(value: Option<usize>) -> TokenStream2 {
+    match value {
+        Some(value) => quote!(::core::option::Option::Some(#value)),
+        None => quote!(::core::option::Option::None)
+    }
+}
+
+fn expand(input: &DeriveInput) -> syn::Result<TokenStream2> {
+    let name = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let attrs = container_attrs(input)?;
+
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => return Err(syn::Error::new_spanned(name, "RdmParameter can only be derived for structs"))
+    };
+
+    let mut min_len = Vec::new();
+    let mut encode = Vec::new();
+    let mut decode = Vec::new();
+    let mut members = Vec::new();
+    let mut checks = Vec::new();
+    let mut trailing = quote!(false);
+
+    for (index, field) in fields.iter().enumerate() {
+        let encoding = field_encoding(field)?;
+
+        if encoding.is_trailing() && index + 1 != fields.len() {
+            return Err(syn::Error::new_spanned(field, "a variable length field runs to the end of the parameter data, so it has to be the last field"));
+        }
+
+        let member = match &field.ident {
+            Some(ident) => Member::Named(ident.clone()),
+            None => Member::from(index)
+        };
+        let local = format_ident!("field_{}", index);
+        let ty = &field.ty;
+        let last = index + 1 == fields.len();
+
+        if last {
+            trailing = match encoding {
+                Encoding::Field => quote!(<#ty as ::e1_20::PdField>::TRAILING),
+                Encoding::FixedString { .. } => quote!(false),
+                Encoding::String { .. } | Encoding::Repeated { .. } => quote!(true)
+            };
+        }
+
+        match encoding {
+            Encoding::Field => {
+                // Only known from the field's own PdField impl, so left to the compiler.  A
+                // generic struct cannot name its parameters outside the impl, so is not checked.
+                if !last && input.generics.params.is_empty() {
+                    let message = format!("{} ends in a variable length field, so it has to be the last field of {}", quote!(#ty), name);
+                    checks.push(quote!(const _ : () = ::core::assert!(!<#ty as ::e1_20::PdField>::TRAILING, #message);));
+                }
+
+                min_len.push(quote!(<#ty as ::e1_20::PdField>::MIN_LEN));
+                encode.push(quote!(::e1_20::PdField::encode(&self.#member, out)?;));
+                decode.push(quote!(let #local = <#ty as ::e1_20::PdField>::decode(data)?;));
+            },
+            Encoding::FixedString { len } => {
+                min_len.push(quote!(#len));
+                encode.push(quote!(::e1_20::pd::encode_fixed_string(&self.#member, #len, out)?;));
+                decode.push(quote!(let #local = ::e1_20::pd::decode_fixed_string(data, #len)?;));
+            },
+            Encoding::String { max_len } => {
+                let max_len = option_tokens(max_len);
+                encode.push(quote!(::e1_20::pd::encode_string(&self.#member, #max_len, out)?;));
+                decode.push(quote!(let #local = ::e1_20::pd::decode_string(data, #max_len);));
+            },
+            Encoding::Repeated { max_len } => {
+                let max_len = option_tokens(max_len);
+                encode.push(quote!(::e1_20::pd::encode_repeated(&self.#member, #max_len, out)?;));
+                decode.push(quote!(let #local = ::e1_20::pd::decode_repeated(data, #max_len)?;));
+            }
+        }
+
+        members.push((member, local));
+    }
+
+    let construct = match fields {
+        Fields::Named(_) => {
+            let fields = members.iter().map(|(member, local)| quote!(#member: #local));
+            quote!(#name { #(#fields),* })
+        },
+        Fields::Unnamed(_) => {
+            let locals = members.iter().map(|(_, local)| local);
+            quote!(#name(#(#locals),*))
+        },
+        Fields::Unit => quote!(#name)
+    };
+
+    let parameter_data = attrs.pid.map(|pid| {
+        let unsupported = quote!(::e1_20::Unsupported);
+
+        let (command_class, get_request, get_response, set_request, set_response) = match (attrs.get, attrs.set) {
+            (true, false) => (quote!(Get), quote!(()), quote!(Self), unsupported.clone(), unsupported),
+            (false, true) => (quote!(Set), unsupported.clone(), unsupported, quote!(Self), quote!(())),
+            _ => (quote!(GetSet), quote!(()), quote!(Self), quote!(Self), quote!(()))
+        };
+
+        let or_default = |ty: Option<Type>, default: TokenStream2| ty.map_or(default, |ty| quote!(#ty));
+        let get_request = or_default(attrs.get_request, get_request);
+        let get_response = or_default(attrs.get_response, get_response);
+        let set_request = or_default(attrs.set_request, set_request);
+        let set_response = or_default(attrs.set_response, set_response);
+
+        quote! {
+            impl #impl_generics ::e1_20::ParameterData for #name #ty_generics #where_clause {
+                const PID : u16 = #pid;
+                const COMMAND_CLASS : ::e1_20::ParameterCommandClass = ::e1_20::ParameterCommandClass::#command_class;
+
+                type GetRequest = #get_request;
+                type GetResponse = #get_response;
+                type SetRequest = #set_request;
+                type SetResponse = #set_response;
+            }
+        }
+    });
+
+    Ok(quote! {
+        #(#checks)*
+
+        impl #impl_generics ::e1_20::PdField for #name #ty_generics #where_clause {
+            const MIN_LEN : usize = 0 #(+ #min_len)*;
+            const TRAILING : bool = #trailing;
+
+            fn encode(&self, out: &mut ::std::vec::Vec<u8>) -> ::core::result::Result<(), ::e1_20::RdmError> {
+                #(#encode)*
+                ::core::result::Result::Ok(())
+            }
+
+            fn decode(data: &mut &[u8]) -> ::core::result::Result<Self, ::e1_20::RdmError> {
+                #(#decode)*
+                ::core::result::Result::Ok(#construct)
+            }
+        }
+
+        impl #impl_generics ::e1_20::PdCodec for #name #ty_generics #where_clause {
+            fn serialize(&self) -> ::core::result::Result<::std::vec::Vec<u8>, ::e1_20::RdmError> {
+                ::e1_20::pd::serialize(self)
+            }
+
+            fn deserialize(data: ::std::vec::Vec<u8>) -> ::core::result::Result<Self, ::e1_20::RdmError> {
+                ::e1_20::pd::deserialize(&data)
+            }
+        }
+
+        #parameter_data
+    })
+}
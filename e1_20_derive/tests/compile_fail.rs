@@ -0,0 +1,6 @@
+//! Attributes and field layouts the derive has to refuse
+
+#[test]
+fn test_compile_fail() {
+    trybuild::TestCases::new().compile_fail("tests/ui/*.rs");
+}
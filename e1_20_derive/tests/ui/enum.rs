@@ -0,0 +1,9 @@
+use e1_20::prelude::*;
+
+#[derive(RdmParameter)]
+enum Mode {
+    Off,
+    On
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use e1_20::prelude::*;
+
+#[derive(RdmParameter)]
+#[rdm(get, get_request = u8)]
+struct Level {
+    level: u8
+}
+
+fn main() {}
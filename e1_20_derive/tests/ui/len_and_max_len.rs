@@ -0,0 +1,9 @@
+use e1_20::prelude::*;
+
+#[derive(RdmParameter)]
+struct Name {
+    #[rdm(len = 8, max_len = 32)]
+    name: String
+}
+
+fn main() {}
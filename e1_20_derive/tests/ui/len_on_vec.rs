@@ -0,0 +1,9 @@
+use e1_20::prelude::*;
+
+#[derive(RdmParameter)]
+struct Levels {
+    #[rdm(len = 4)]
+    levels: Vec<u8>
+}
+
+fn main() {}
@@ -0,0 +1,15 @@
+use e1_20::prelude::*;
+
+#[derive(RdmParameter)]
+struct Label {
+    #[rdm(max_len = 32)]
+    text: String
+}
+
+#[derive(RdmParameter)]
+struct Labelled {
+    label: Label,
+    priority: u8
+}
+
+fn main() {}
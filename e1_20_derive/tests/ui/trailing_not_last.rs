@@ -0,0 +1,10 @@
+use e1_20::prelude::*;
+
+#[derive(RdmParameter)]
+struct Slots {
+    #[rdm(max_len = 4)]
+    slots: Vec<u16>,
+    count: u8
+}
+
+fn main() {}
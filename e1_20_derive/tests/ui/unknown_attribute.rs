@@ -0,0 +1,9 @@
+use e1_20::prelude::*;
+
+#[derive(RdmParameter)]
+#[rdm(pid = 0x8001, gett)]
+struct Level {
+    level: u8
+}
+
+fn main() {}
@@ -0,0 +1,9 @@
+use e1_20::prelude::*;
+
+#[derive(RdmParameter)]
+struct Name {
+    #[rdm(length = 8)]
+    name: String
+}
+
+fn main() {}
@@ -0,0 +1,1087 @@
+//! GET and SET against a single responder
+//! Handles the parts of a transaction the caller should not have to think about: TN
+//! bookkeeping, matching the response to the request, ACK_OVERFLOW reassembly and collecting
+//! ACK_TIMER responses from QUEUED_MESSAGE.
+//! As with discovery, the async functions hold the logic and the blocking ones wrap them.
+//! RdmController puts a typed API over them for a port with its own UID, TN and retry policy,
+//! and adds broadcast and vendorcast requests, which no device answers.
+
+use std::time::Instant;
+
+use crate::*;
+
+/// Largest response the controller will reassemble from ACK_OVERFLOW chunks.
+/// A proxy reporting 1000 devices in PROXIED_DEVICES needs 6000 bytes.
+pub const MAX_OVERFLOW_LENGTH : usize = 8192;
+
+/// How many times QUEUED_MESSAGE is polled for a deferred response before giving up
+pub const MAX_QUEUED_MESSAGE_POLLS : usize = 20;
+
+/// How many ACK_TIMER responses one request may wait out before giving up
+pub const MAX_ACK_TIMER_WAITS : usize = 10;
+
+/// Wait between QUEUED_MESSAGE polls when the device has nothing for us yet
+pub const QUEUED_MESSAGE_RETRY : Duration = Duration::from_millis(100);
+
+/// Status type sent with QUEUED_MESSAGE while waiting for a deferred response.
+/// STATUS_ADVISORY is the lowest threshold, so any status the device reports instead of our
+/// response is at least visible in the debug log.
+pub const QUEUED_MESSAGE_STATUS_TYPE : u8 = STATUS_ADVISORY;
+
+/// Minimum bus idle after a broadcast or vendorcast request before the next frame (E1.20 Table 3-2)
+pub const BROADCAST_IDLE : Duration = Duration::from_micros(176);
+
+/// Reads the estimated response time out of an ACK_TIMER response
+pub fn decode_ack_timer(pd: &[u8]) -> Result<Duration, RdmError> {
+    if pd.len() < 2 {
+        return Err(RdmError::TooShort { needed: 2, got: pd.len() });
+    }
+
+    let tenths = u16::from_be_bytes([pd[0], pd[1]]);
+
+    Ok(Duration::from_millis(tenths as u64 * 100))
+}
+
+/// Checks that response comes from the device we asked and carries our TN
+fn check_addressing(request: &Pkt, response: &PktRef) -> Result<(), RdmError> {
+    if response.source() != request.destination
+        || response.destination() != request.source
+        || response.tn() != request.tn {
+        return Err(RdmError::UnexpectedResponse);
+    }
+
+    Ok(())
+}
+
+/// True if a frame with pid and cc answers request, whatever its TN
+fn answers(request: &Pkt, pid: u16, cc: u8) -> Result<bool, RdmError> {
+    let expected_cc = request.command_class()?
+        .response()
+        .ok_or(RdmError::UnknownCommandClass(request.cc))?;
+
+    Ok(pid == request.pid && cc == u8::from(expected_cc))
+}
+
+/// Checks that response answers request: it must come from the device we asked, carry our TN
+/// and PID and use the matching response command class.  do_request checks every direct
+/// response with this; the answers to QUEUED_MESSAGE polls are sorted out there.
+pub fn check_response(request: &Pkt, response: &PktRef) -> Result<(), RdmError> {
+    let answers = answers(request, response.pid(), response.cc())?;
+
+    check_addressing(request, response)?;
+
+    if !answers {
+        return Err(RdmError::UnexpectedResponse);
+    }
+
+    Ok(())
+}
+
+/// Sends request and waits for a response from the right device with the right TN.
+/// With full_match the response must also answer request's PID and command class; a
+/// QUEUED_MESSAGE poll is answered with other PIDs, so that is left to the caller.
+async fn do_transaction<T: AsyncRdmTransport + ?Sized>(transport: &mut T, request: &Pkt, full_match: bool) -> Result<Pkt, RdmError> {
+    let mut buf = [0u8; RDM_MAX_FRAME_LENGTH];
+    let len = request.encode_into(&mut buf)?;
+
+    let data = transport.send_request(&buf[..len]).await?.ok_or(RdmError::NoResponse)?;
+    let response = PktRef::new(&data)?;
+
+    if full_match {
+        check_response(request, &response)?;
+    } else {
+        check_addressing(request, &response)?;
+    }
+
+    Ok(response.to_pkt())
+}
+
+/// Retry policy for the frames of a request
+#[derive(Debug, Clone, Copy)]
+struct RetryPolicy {
+    retries: u8,
+    retry_delay: Duration,
+    timeout: Option<Duration>
+}
+
+impl RetryPolicy {
+    /// Every frame is sent once
+    const NONE : RetryPolicy = RetryPolicy { retries: 0, retry_delay: Duration::ZERO, timeout: None };
+}
+
+/// True for the errors a retry can fix: no response, a frame that does not decode, or one that
+/// does not answer the request
+fn is_retryable(error: &RdmError) -> bool {
+    matches!(error,
+        RdmError::NoResponse
+        | RdmError::UnexpectedResponse
+        | RdmError::TooShort { .. }
+        | RdmError::BadStartCode(_)
+        | RdmError::BadSubStartCode(_)
+        | RdmError::LengthMismatch { .. }
+        | RdmError::PdlOverrun { .. }
+        | RdmError::BadChecksum { .. }
+        | RdmError::UnknownResponseType(_))
+}
+
+/// Sends one frame of a request with the next TN, and sends that frame again with a new TN
+/// while nothing usable comes back and policy allows.  A frame that fails to decode or does not
+/// answer is a stray, e.g. a late response to an earlier TN or line noise, and is discarded.
+/// started is when the whole request began, for policy.timeout.
+async fn do_frame<T: AsyncRdmTransport + ?Sized>(transport: &mut T, policy: &RetryPolicy, frame: &PktBuilder, full_match: bool, tn: &mut u8, started: Instant) -> Result<Pkt, RdmError> {
+    let mut attempt : u8 = 0;
+
+    loop {
+        *tn = tn.overflowing_add(1).0;
+
+        let sent = frame.clone().tn(*tn).build()?;
+
+        let error = match do_transaction(transport, &sent, full_match).await {
+            Ok(response) => return Ok(response),
+            Err(e) if !is_retryable(&e) => return Err(e),
+            Err(e) => e
+        };
+
+        if attempt >= policy.retries {
+            return Err(error);
+        }
+
+        if let Some(timeout) = policy.timeout {
+            if started.elapsed() >= timeout {
+                return Err(RdmError::RequestTimeout(timeout));
+            }
+        }
+
+        attempt += 1;
+        debug!("do_frame: discarding response to {} ({}), retry {}", sent.parameter_id(), error, attempt);
+
+        transport.sleep(policy.retry_delay).await;
+    }
+}
+
+/// Sends request and returns the parameter data of the response.
+/// ACK_OVERFLOW responses are followed by re-issuing the same request until the final ACK; the
+/// chunks are concatenated so the result can go straight to the PD decoders.
+/// ACK_TIMER responses are followed by waiting out the estimate and then polling
+/// QUEUED_MESSAGE until the deferred response for our PID comes back.  Other queued messages
+/// that come back meanwhile are added to queued; while the device says it has more, the next
+/// poll goes out straight away.
+/// policy retries only the frame that went unanswered, so once the device has answered
+/// request it is never sent again except to continue an ACK_OVERFLOW.
+/// Also returns the message count of the final response.
+async fn do_request<T: AsyncRdmTransport + ?Sized>(transport: &mut T, policy: &RetryPolicy, request: PktBuilder, tn: &mut u8, queued: &mut Vec<Pkt>) -> Result<(Vec<u8>, u8), RdmError> {
+    // A request that cannot be framed fails before anything is sent
+    let original = request.clone().build()?;
+    let started = Instant::now();
+
+    let mut collected : Vec<u8> = Vec::new();
+
+    // Once the device has sent ACK_TIMER, every request after that is a QUEUED_MESSAGE poll
+    let mut queued_message : Option<PktBuilder> = None;
+    let mut polls : usize = 0;
+    let mut timer_waits : usize = 0;
+
+    loop {
+        let response = match &queued_message {
+            None => do_frame(transport, policy, &request, true, tn, started).await?,
+            Some(poll) => do_frame(transport, policy, poll, false, tn, started).await?
+        };
+
+        // check_response has matched a direct response already; a poll can bring back anything
+        if queued_message.is_some() && !answers(&original, response.pid, response.cc)? {
+            if response.pid == QUEUED_MESSAGE && response.port_or_response_type == RESPONSE_TYPE_NACK_REASON {
+                return Err(RdmError::Nack(NackReason::decode(&response.pd)?));
+            }
+
+            // The device needs longer to answer the poll itself
+            if response.pid == QUEUED_MESSAGE && response.port_or_response_type == RESPONSE_TYPE_ACK_TIMER {
+                timer_waits += 1;
+                if timer_waits > MAX_ACK_TIMER_WAITS {
+                    return Err(RdmError::QueuedMessageTimeout);
+                }
+
+                transport.sleep(decode_ack_timer(&response.pd)?).await;
+                continue;
+            }
+
+            polls += 1;
+            if polls >= MAX_QUEUED_MESSAGE_POLLS {
+                return Err(RdmError::QueuedMessageTimeout);
+            }
+
+            // An empty STATUS_MESSAGES means nothing is queued
+            if response.pid == STATUS_MESSAGES && response.pd.is_empty() {
+                transport.sleep(QUEUED_MESSAGE_RETRY).await;
+                continue;
+            }
+
+            let more = response.message_count > 0;
+
+            // Only a complete GET or SET response is a message for the caller
+            if response.port_or_response_type == RESPONSE_TYPE_ACK
+                && matches!(response.command_class(), Ok(CommandClass::GetCommandResponse | CommandClass::SetCommandResponse)) {
+                debug!("do_request: waiting on {} from {}, QUEUED_MESSAGE returned {}", original.parameter_id(), original.destination, response.parameter_id());
+                queued.push(response);
+            } else {
+                debug!("do_request: waiting on {} from {}, ignoring {} with response type {} from QUEUED_MESSAGE", original.parameter_id(), original.destination, response.parameter_id(), response.port_or_response_type);
+            }
+
+            if !more {
+                transport.sleep(QUEUED_MESSAGE_RETRY).await;
+            }
+            continue;
+        }
+
+        match RdmResponse::decode_raw(&response)? {
+            RdmResponse::Ack(pd) => {
+                collected.extend(pd);
+                return Ok((collected, response.message_count));
+            },
+            RdmResponse::AckOverflow(chunk) => {
+                collected.extend(chunk);
+
+                if collected.len() > MAX_OVERFLOW_LENGTH {
+                    return Err(RdmError::OverflowLimit(MAX_OVERFLOW_LENGTH));
+                }
+
+                debug!("do_request: {} sent ACK_OVERFLOW for {}, {} bytes so far", original.destination, original.parameter_id(), collected.len());
+            },
+            RdmResponse::Nack(reason) => {
+                if collected.is_empty() {
+                    return Err(RdmError::Nack(reason));
+                }
+
+                return Err(RdmError::NackDuringOverflow { reason, received: collected.len() });
+            },
+            RdmResponse::AckTimer(delay) => {
+                debug!("do_request: {} sent ACK_TIMER for {}, waiting {:?}", original.destination, original.parameter_id(), delay);
+
+                timer_waits += 1;
+                if timer_waits > MAX_ACK_TIMER_WAITS {
+                    return Err(RdmError::QueuedMessageTimeout);
+                }
+
+                queued_message = Some(RdmRequest::get(original.destination, QUEUED_MESSAGE)
+                    .source(original.source)
+                    .pd(&[QUEUED_MESSAGE_STATUS_TYPE]));
+
+                transport.sleep(delay).await;
+            }
+        }
+    }
+}
+
+/// GETs pid from uid and returns the response parameter data, reassembling ACK_OVERFLOW
+/// responses.  Pass the result to the matching PD decoder, e.g. DeviceInfoPD::deserialize.
+/// Responses to other PIDs that the device sends from its queue while we wait on ACK_TIMER are
+/// added to queued.
+#[allow(clippy::too_many_arguments)]
+pub fn do_get<T: RdmTransport + ?Sized>(transport: &mut T, my_uid: &Uid, uid: &Uid, sub_device: u16, pid: u16, pd: &[u8], tn: &mut u8, queued: &mut Vec<Pkt>) -> Result<Vec<u8>, RdmError> {
+    block_on(do_get_async(&mut BlockingTransport(transport), my_uid, uid, sub_device, pid, pd, tn, queued))
+}
+
+/// SETs pid on uid and returns the response parameter data
+#[allow(clippy::too_many_arguments)]
+pub fn do_set<T: RdmTransport + ?Sized>(transport: &mut T, my_uid: &Uid, uid: &Uid, sub_device: u16, pid: u16, pd: &[u8], tn: &mut u8, queued: &mut Vec<Pkt>) -> Result<Vec<u8>, RdmError> {
+    block_on(do_set_async(&mut BlockingTransport(transport), my_uid, uid, sub_device, pid, pd, tn, queued))
+}
+
+/// Async version of do_get
+/// tn is advanced before each frame is sent, so a cancelled GET never reuses a TN.
+#[allow(clippy::too_many_arguments)]
+pub async fn do_get_async<T: AsyncRdmTransport + ?Sized>(transport: &mut T, my_uid: &Uid, uid: &Uid, sub_device: u16, pid: u16, pd: &[u8], tn: &mut u8, queued: &mut Vec<Pkt>) -> Result<Vec<u8>, RdmError> {
+    do_request(transport, &RetryPolicy::NONE, RdmRequest::get(*uid, pid).source(*my_uid).sub_device(sub_device).pd(pd), tn, queued).await
+        .map(|(pd, _)| pd)
+}
+
+/// Async version of do_set
+#[allow(clippy::too_many_arguments)]
+pub async fn do_set_async<T: AsyncRdmTransport + ?Sized>(transport: &mut T, my_uid: &Uid, uid: &Uid, sub_device: u16, pid: u16, pd: &[u8], tn: &mut u8, queued: &mut Vec<Pkt>) -> Result<Vec<u8>, RdmError> {
+    do_request(transport, &RetryPolicy::NONE, RdmRequest::set(*uid, pid).source(*my_uid).sub_device(sub_device).pd(pd), tn, queued).await
+        .map(|(pd, _)| pd)
+}
+
+/// Sends request to a broadcast or vendorcast address and then holds the line for idle.
+/// Nothing will answer, so nothing is waited for.
+async fn do_broadcast<T: AsyncRdmTransport + ?Sized>(transport: &mut T, request: PktBuilder, tn: &mut u8, idle: Duration) -> Result<(), RdmError> {
+    *tn = tn.overflowing_add(1).0;
+
+    let frame = request.tn(*tn).build()?;
+
+    if !frame.destination.is_broadcast() && !frame.destination.is_vendorcast() {
+        return Err(RdmError::NotBroadcast(frame.destination));
+    }
+
+    let mut buf = [0u8; RDM_MAX_FRAME_LENGTH];
+    let len = frame.encode_into(&mut buf)?;
+
+    transport.send_broadcast(&buf[..len]).await?;
+    transport.sleep(idle).await;
+
+    Ok(())
+}
+
+/// Outcome of reading a parameter back from every device a broadcast reached
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct BroadcastVerification {
+    /// Devices that report the expected value
+    pub confirmed: Vec<Uid>,
+    /// Devices that answered with something else
+    pub mismatched: Vec<Uid>,
+    /// Devices whose GET failed, e.g. no response or a NACK
+    pub failed: Vec<(Uid, RdmError)>
+}
+
+impl BroadcastVerification {
+    /// Every device confirmed the value
+    pub fn is_confirmed(&self) -> bool {
+        self.mismatched.is_empty() && self.failed.is_empty()
+    }
+}
+
+/// A controller on one port: owns the transport, our UID, the port's TN counter and the retry
+/// policy, and turns GET and SET into typed calls.
+///
+/// The blocking methods need an RdmTransport and the _async ones an AsyncRdmTransport.
+pub struct RdmController<T> {
+    transport: T,
+    uid: Uid,
+    tn: u8,
+    message_count: u8,
+    queued: Vec<Pkt>,
+    policy: RetryPolicy,
+    broadcast_idle: Duration
+}
+
+impl<T> RdmController<T> {
+    /// A controller sending as uid, with no retries
+    pub fn new(transport: T, uid: Uid) -> RdmController<T> {
+        RdmController {
+            transport,
+            uid,
+            tn: 0,
+            message_count: 0,
+            queued: Vec::new(),
+            policy: RetryPolicy::NONE,
+            broadcast_idle: BROADCAST_IDLE
+        }
+    }
+
+    /// Extra attempts at each frame that gets no response, or only a stray.  Off by default,
+    /// since a SET whose ACK was lost has still been applied and a retry applies it again.
+    pub fn retries(mut self, retries: u8) -> RdmController<T> {
+        self.policy.retries = retries;
+        self
+    }
+
+    /// Wait before each retry
+    pub fn retry_delay(mut self, retry_delay: Duration) -> RdmController<T> {
+        self.policy.retry_delay = retry_delay;
+        self
+    }
+
+    /// Stop retrying once a call has taken this long.  Checked between attempts, so the
+    /// transport's own timeout still bounds each frame.
+    pub fn timeout(mut self, timeout: Duration) -> RdmController<T> {
+        self.policy.timeout = Some(timeout);
+        self
+    }
+
+    /// Bus idle after each broadcast, for responders that need longer than BROADCAST_IDLE
+    /// to act on one
+    pub fn broadcast_idle(mut self, broadcast_idle: Duration) -> RdmController<T> {
+        self.broadcast_idle = broadcast_idle;
+        self
+    }
+
+    pub fn uid(&self) -> Uid {
+        self.uid
+    }
+
+    /// TN of the last frame sent
+    pub fn tn(&self) -> u8 {
+        self.tn
+    }
+
+    /// Message count of the last response: how many messages the device has queued for
+    /// QUEUED_MESSAGE.  Not changed by calls that fail.
+    pub fn message_count(&self) -> u8 {
+        self.message_count
+    }
+
+    /// Queued messages the device sent for other PIDs while a call waited on ACK_TIMER, oldest
+    /// first.  Taking them clears the list.
+    pub fn take_queued(&mut self) -> Vec<Pkt> {
+        std::mem::take(&mut self.queued)
+    }
+
+    fn record(&mut self, result: Result<(Vec<u8>, u8), RdmError>) -> Result<Vec<u8>, RdmError> {
+        let (pd, message_count) = result?;
+        self.message_count = message_count;
+        Ok(pd)
+    }
+
+    pub fn transport(&self) -> &T {
+        &self.transport
+    }
+
+    pub fn transport_mut(&mut self) -> &mut T {
+        &mut self.transport
+    }
+
+    pub fn into_transport(self) -> T {
+        self.transport
+    }
+
+    /// Files the result of a verification GET; only a transport failure stops the run
+    fn check_verification<P: PartialEq>(verification: &mut BroadcastVerification, uid: Uid, result: Result<P, RdmError>, expected: &P) -> Result<(), RdmError> {
+        match result {
+            Ok(value) if value == *expected => verification.confirmed.push(uid),
+            Ok(_) => verification.mismatched.push(uid),
+            Err(RdmError::Transport(e)) => return Err(RdmError::Transport(e)),
+            Err(e) => verification.failed.push((uid, e))
+        }
+
+        Ok(())
+    }
+}
+
+impl<T: RdmTransport> RdmController<T> {
+    /// GETs P from uid, for PIDs whose GET carries no parameter data
+    pub fn get<P: ParameterData<GetRequest = ()>>(&mut self, uid: &Uid, sub_device: u16) -> Result<P::GetResponse, RdmError> {
+        self.get_with::<P>(uid, sub_device, &())
+    }
+
+    /// GETs P from uid with request parameter data, e.g. the sensor number of SENSOR_VALUE
+    pub fn get_with<P: ParameterData>(&mut self, uid: &Uid, sub_device: u16, request: &P::GetRequest) -> Result<P::GetResponse, RdmError> {
+        P::GetResponse::deserialize(self.get_raw(uid, sub_device, P::PID, &request.serialize()?)?)
+    }
+
+    /// SETs P on uid
+    pub fn set<P: ParameterData>(&mut self, uid: &Uid, sub_device: u16, value: &P::SetRequest) -> Result<P::SetResponse, RdmError> {
+        P::SetResponse::deserialize(self.set_raw(uid, sub_device, P::PID, &value.serialize()?)?)
+    }
+
+    /// SETs P on every device on the line.  Nobody answers a broadcast, so this returns once
+    /// the frame is sent and the bus idle has passed.
+    pub fn broadcast_set<P: ParameterData>(&mut self, sub_device: u16, value: &P::SetRequest) -> Result<(), RdmError> {
+        self.broadcast_set_raw(&Uid::BROADCAST, sub_device, P::PID, &value.serialize()?)
+    }
+
+    /// SETs P on every device made by manufacturer mfg
+    pub fn vendorcast_set<P: ParameterData>(&mut self, mfg: u16, sub_device: u16, value: &P::SetRequest) -> Result<(), RdmError> {
+        self.broadcast_set_raw(&Uid::vendorcast(mfg), sub_device, P::PID, &value.serialize()?)
+    }
+
+    /// Sends a GET for pid to a broadcast or vendorcast destination
+    pub fn broadcast_get_raw(&mut self, destination: &Uid, sub_device: u16, pid: u16, pd: &[u8]) -> Result<(), RdmError> {
+        let request = RdmRequest::get(*destination, pid).source(self.uid).sub_device(sub_device).pd(pd);
+        block_on(do_broadcast(&mut BlockingTransport(&mut self.transport), request, &mut self.tn, self.broadcast_idle))
+    }
+
+    /// Sends a SET of pid to a broadcast or vendorcast destination
+    pub fn broadcast_set_raw(&mut self, destination: &Uid, sub_device: u16, pid: u16, pd: &[u8]) -> Result<(), RdmError> {
+        let request = RdmRequest::set(*destination, pid).source(self.uid).sub_device(sub_device).pd(pd);
+        block_on(do_broadcast(&mut BlockingTransport(&mut self.transport), request, &mut self.tn, self.broadcast_idle))
+    }
+
+    /// GETs P from every device in tod that a broadcast to destination reached, and compares
+    /// it with expected.  Devices that fail to answer are reported rather than returned as an
+    /// error; only a transport failure stops the run.
+    pub fn verify<P: ParameterData<GetRequest = ()>>(&mut self, destination: &Uid, tod: &[Uid], sub_device: u16, expected: &P::GetResponse) -> Result<BroadcastVerification, RdmError>
+        where P::GetResponse: PartialEq {
+        let mut verification = BroadcastVerification::default();
+
+        for uid in tod.iter().filter(|uid| destination.addresses(uid)) {
+            let result = self.get::<P>(uid, sub_device);
+            Self::check_verification(&mut verification, *uid, result, expected)?;
+        }
+
+        Ok(verification)
+    }
+
+    /// GETs pid with request parameter data pd, for parameters without a ParameterData type
+    pub fn get_raw(&mut self, uid: &Uid, sub_device: u16, pid: u16, pd: &[u8]) -> Result<Vec<u8>, RdmError> {
+        let request = RdmRequest::get(*uid, pid).source(self.uid).sub_device(sub_device).pd(pd);
+        let result = block_on(do_request(&mut BlockingTransport(&mut self.transport), &self.policy, request, &mut self.tn, &mut self.queued));
+        self.record(result)
+    }
+
+    /// SETs pid to pd and returns the response parameter data
+    pub fn set_raw(&mut self, uid: &Uid, sub_device: u16, pid: u16, pd: &[u8]) -> Result<Vec<u8>, RdmError> {
+        let request = RdmRequest::set(*uid, pid).source(self.uid).sub_device(sub_device).pd(pd);
+        let result = block_on(do_request(&mut BlockingTransport(&mut self.transport), &self.policy, request, &mut self.tn, &mut self.queued));
+        self.record(result)
+    }
+}
+
+impl<T: AsyncRdmTransport> RdmController<T> {
+    /// Async version of get
+    pub async fn get_async<P: ParameterData<GetRequest = ()>>(&mut self, uid: &Uid, sub_device: u16) -> Result<P::GetResponse, RdmError> {
+        self.get_with_async::<P>(uid, sub_device, &()).await
+    }
+
+    /// Async version of get_with
+    pub async fn get_with_async<P: ParameterData>(&mut self, uid: &Uid, sub_device: u16, request: &P::GetRequest) -> Result<P::GetResponse, RdmError> {
+        P::GetResponse::deserialize(self.get_raw_async(uid, sub_device, P::PID, &request.serialize()?).await?)
+    }
+
+    /// Async version of set
+    pub async fn set_async<P: ParameterData>(&mut self, uid: &Uid, sub_device: u16, value: &P::SetRequest) -> Result<P::SetResponse, RdmError> {
+        P::SetResponse::deserialize(self.set_raw_async(uid, sub_device, P::PID, &value.serialize()?).await?)
+    }
+
+    /// Async version of broadcast_set
+    pub async fn broadcast_set_async<P: ParameterData>(&mut self, sub_device: u16, value: &P::SetRequest) -> Result<(), RdmError> {
+        self.broadcast_set_raw_async(&Uid::BROADCAST, sub_device, P::PID, &value.serialize()?).await
+    }
+
+    /// Async version of vendorcast_set
+    pub async fn vendorcast_set_async<P: ParameterData>(&mut self, mfg: u16, sub_device: u16, value: &P::SetRequest) -> Result<(), RdmError> {
+        self.broadcast_set_raw_async(&Uid::vendorcast(mfg), sub_device, P::PID, &value.serialize()?).await
+    }
+
+    /// Async version of broadcast_get_raw
+    pub async fn broadcast_get_raw_async(&mut self, destination: &Uid, sub_device: u16, pid: u16, pd: &[u8]) -> Result<(), RdmError> {
+        let request = RdmRequest::get(*destination, pid).source(self.uid).sub_device(sub_device).pd(pd);
+        do_broadcast(&mut self.transport, request, &mut self.tn, self.broadcast_idle).await
+    }
+
+    /// Async version of broadcast_set_raw
+    pub async fn broadcast_set_raw_async(&mut self, destination: &Uid, sub_device: u16, pid: u16, pd: &[u8]) -> Result<(), RdmError> {
+        let request = RdmRequest::set(*destination, pid).source(self.uid).sub_device(sub_device).pd(pd);
+        do_broadcast(&mut self.transport, request, &mut self.tn, self.broadcast_idle).await
+    }
+
+    /// Async version of verify
+    pub async fn verify_async<P: ParameterData<GetRequest = ()>>(&mut self, destination: &Uid, tod: &[Uid], sub_device: u16, expected: &P::GetResponse) -> Result<BroadcastVerification, RdmError>
+        where P::GetResponse: PartialEq {
+        let mut verification = BroadcastVerification::default();
+
+        for uid in tod.iter().filter(|uid| destination.addresses(uid)) {
+            let result = self.get_async::<P>(uid, sub_device).await;
+            Self::check_verification(&mut verification, *uid, result, expected)?;
+        }
+
+        Ok(verification)
+    }
+
+    /// Async version of get_raw
+    pub async fn get_raw_async(&mut self, uid: &Uid, sub_device: u16, pid: u16, pd: &[u8]) -> Result<Vec<u8>, RdmError> {
+        let request = RdmRequest::get(*uid, pid).source(self.uid).sub_device(sub_device).pd(pd);
+        let result = do_request(&mut self.transport, &self.policy, request, &mut self.tn, &mut self.queued).await;
+        self.record(result)
+    }
+
+    /// Async version of set_raw
+    pub async fn set_raw_async(&mut self, uid: &Uid, sub_device: u16, pid: u16, pd: &[u8]) -> Result<Vec<u8>, RdmError> {
+        let request = RdmRequest::set(*uid, pid).source(self.uid).sub_device(sub_device).pd(pd);
+        let result = do_request(&mut self.transport, &self.policy, request, &mut self.tn, &mut self.queued).await;
+        self.record(result)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEVICE : Uid = Uid { mfg: 0x1234, dev: 0x56789ABC };
+    const CONTROLLER : Uid = Uid { mfg: 0x044E, dev: 0x00000001 };
+    const SLOW_DEVICE : Uid = Uid { mfg: 0x1234, dev: 0x00000002 };
+    const BUSY_DEVICE : Uid = Uid { mfg: 0x1234, dev: 0x00000003 };
+
+    /// 600 bytes of SUPPORTED_PARAMETERS split over three frames
+    fn overflow_payload() -> Vec<u8> {
+        (0..600u16).map(|i| i as u8).collect()
+    }
+
+    /// The TN tells the responder which chunk is being asked for: the tests start at 0 so the
+    /// first request carries TN 1.
+    fn overflow_responder(data: &[u8]) -> Option<Vec<u8>> {
+        let request = Pkt::deserialize(data.to_vec()).ok()?;
+        let payload = overflow_payload();
+
+        let response = match (request.pid, request.tn) {
+            (SUPPORTED_PARAMETERS, 1) => Pkt::ack_overflow(&request, &payload[0..230], 0),
+            (SUPPORTED_PARAMETERS, 2) => Pkt::ack_overflow(&request, &payload[230..460], 0),
+            (SUPPORTED_PARAMETERS, 3) => Pkt::ack(&request, &payload[460..], 0),
+            (PROXIED_DEVICES, 1) => Pkt::ack_overflow(&request, &payload[0..228], 0),
+            (PROXIED_DEVICES, _) => Pkt::nack(&request, NackReason::HardwareFault, 0),
+            (DEVICE_LABEL, _) => Pkt::nack(&request, NackReason::WriteProtect, 0),
+            (SLOT_INFO, _) => Pkt::ack_overflow(&request, &[0u8; 231], 0),
+            (DEVICE_INFO, _) => {
+                let mut response = Pkt::ack(&request, &[], 0).ok()?;
+                response.tn = response.tn.wrapping_add(1);
+                response.set_checksum();
+                Ok(response)
+            },
+            _ => return None
+        };
+
+        Some(response.ok()?.serialize())
+    }
+
+    /// RESET_DEVICE: ACK_TIMER, then an empty queue, then the deferred ACK.
+    /// FACTORY_DEFAULTS: ACK_TIMER, then QUEUED_MESSAGE is NACKed.
+    /// SLOW_DEVICE never has anything queued.
+    /// BUSY_DEVICE queues a DMX_START_ADDRESS response, with more to come, then puts a poll off
+    /// with ACK_TIMER, then sends part of an ACK_OVERFLOW, with more to come, and a status
+    /// message ahead of the deferred ACK.
+    fn ack_timer_responder(data: &[u8]) -> Option<Vec<u8>> {
+        let request = Pkt::deserialize(data.to_vec()).ok()?;
+        let slow = request.destination == SLOW_DEVICE;
+        let busy = request.destination == BUSY_DEVICE;
+
+        let queued = |pid: u16, response_type: ResponseType, pd: &[u8], message_count: u8| {
+            PktBuilder::new(CommandClass::GetCommandResponse, request.source, pid)
+                .source(request.destination)
+                .tn(request.tn)
+                .response_type(response_type)
+                .message_count(message_count)
+                .pd(pd)
+                .build()
+        };
+
+        let response = match (request.pid, request.tn) {
+            (RESET_DEVICE, 1) | (FACTORY_DEFAULTS, 1) => Pkt::ack_timer(&request, Duration::ZERO, 0),
+            (QUEUED_MESSAGE, 2) if busy => queued(DMX_START_ADDRESS, ResponseType::Ack, &[0x00, 0x01], 1),
+            (QUEUED_MESSAGE, 3) if busy => Pkt::ack_timer(&request, Duration::from_millis(200), 0),
+            (QUEUED_MESSAGE, 4) if busy => queued(SLOT_INFO, ResponseType::AckOverflow, &[0x00; 5], 1),
+            (QUEUED_MESSAGE, 5) if busy => queued(STATUS_MESSAGES, ResponseType::Ack, &[0x00, 0x00, 0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00], 0),
+            (QUEUED_MESSAGE, tn) if request.pd == [QUEUED_MESSAGE_STATUS_TYPE] && (slow || tn == 2) => {
+                PktBuilder::new(CommandClass::GetCommandResponse, request.source, STATUS_MESSAGES)
+                    .source(request.destination)
+                    .tn(request.tn)
+                    .response_type(ResponseType::Ack)
+                    .build()
+            },
+            (QUEUED_MESSAGE, 3 | 6) => {
+                PktBuilder::new(CommandClass::SetCommandResponse, request.source, RESET_DEVICE)
+                    .source(request.destination)
+                    .tn(request.tn)
+                    .response_type(ResponseType::Ack)
+                    .pd(&[0xFF])
+                    .build()
+            },
+            (QUEUED_MESSAGE, _) => Pkt::nack(&request, NackReason::UnknownPid, 0),
+            _ => return None
+        };
+
+        Some(response.ok()?.serialize())
+    }
+
+    /// Sleeps on a virtual clock, so waiting out ACK_TIMER and QUEUED_MESSAGE_RETRY costs nothing
+    struct VirtualClock {
+        responder: fn(&[u8]) -> Option<Vec<u8>>,
+        slept: Duration
+    }
+
+    impl AsyncRdmTransport for VirtualClock {
+        async fn send_request(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>, TransportError> {
+            Ok((self.responder)(data))
+        }
+
+        async fn send_broadcast(&mut self, _data: &[u8]) -> Result<(), TransportError> {
+            Ok(())
+        }
+
+        async fn sleep(&mut self, duration: Duration) {
+            self.slept += duration;
+        }
+    }
+
+    #[test]
+    fn test_ack_timer_follow_up() {
+        assert_eq!(decode_ack_timer(&[0x00, 0x0F]), Ok(Duration::from_millis(1500)));
+        assert_eq!(decode_ack_timer(&[0x01]), Err(RdmError::TooShort { needed: 2, got: 1 }));
+
+        let mut port = VirtualClock { responder: ack_timer_responder, slept: Duration::ZERO };
+        let mut tn = 0;
+        assert_eq!(block_on(do_set_async(&mut port, &CONTROLLER, &DEVICE, 0, RESET_DEVICE, &[0xFF], &mut tn, &mut Vec::new())), Ok(vec![0xFF]));
+        assert_eq!(tn, 3);
+        assert_eq!(port.slept, QUEUED_MESSAGE_RETRY);
+
+        let mut tn = 0;
+        assert_eq!(block_on(do_set_async(&mut port, &CONTROLLER, &DEVICE, 0, FACTORY_DEFAULTS, &[], &mut tn, &mut Vec::new())),
+            Err(RdmError::Nack(NackReason::UnknownPid)));
+
+        // Every poll but the last waits out QUEUED_MESSAGE_RETRY
+        let mut port = VirtualClock { responder: ack_timer_responder, slept: Duration::ZERO };
+        let mut tn = 0;
+        assert_eq!(block_on(do_set_async(&mut port, &CONTROLLER, &SLOW_DEVICE, 0, RESET_DEVICE, &[0x01], &mut tn, &mut Vec::new())),
+            Err(RdmError::QueuedMessageTimeout));
+        assert_eq!(port.slept, QUEUED_MESSAGE_RETRY * (MAX_QUEUED_MESSAGE_POLLS as u32 - 1));
+    }
+
+    #[test]
+    fn test_queued_messages_kept() {
+        // A queued message that says there is more is followed by the next poll straight away,
+        // an ACK_TIMER for the poll itself is waited out, and only complete ACKs are kept
+        let port = VirtualClock { responder: ack_timer_responder, slept: Duration::ZERO };
+        let mut controller = RdmController::new(port, CONTROLLER);
+        assert_eq!(block_on(controller.set_raw_async(&BUSY_DEVICE, 0, RESET_DEVICE, &[0xFF])), Ok(vec![0xFF]));
+        assert_eq!(controller.tn(), 6);
+        assert_eq!(controller.transport().slept, Duration::from_millis(200) + QUEUED_MESSAGE_RETRY);
+
+        let queued = controller.take_queued();
+        assert_eq!(queued.iter().map(|response| response.pid).collect::<Vec<u16>>(), vec![DMX_START_ADDRESS, STATUS_MESSAGES]);
+        assert_eq!(queued[0].pd, vec![0x00, 0x01]);
+        assert!(controller.take_queued().is_empty());
+
+        // The free functions hand them back too
+        let mut port = VirtualClock { responder: ack_timer_responder, slept: Duration::ZERO };
+        let (mut tn, mut queued) = (0, Vec::new());
+        assert_eq!(block_on(do_set_async(&mut port, &CONTROLLER, &BUSY_DEVICE, 0, RESET_DEVICE, &[0xFF], &mut tn, &mut queued)), Ok(vec![0xFF]));
+        assert_eq!(queued.len(), 2);
+    }
+
+    /// Holds state between calls, which a function pointer could not
+    struct CountingTransport {
+        sent: usize,
+        broken: bool
+    }
+
+    impl RdmTransport for CountingTransport {
+        fn send_request(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>, TransportError> {
+            self.sent += 1;
+
+            if self.broken {
+                return Err(TransportError::new(std::io::ErrorKind::BrokenPipe, "port closed"));
+            }
+
+            let request = Pkt::deserialize(data.to_vec()).unwrap();
+            Ok(Some(Pkt::ack(&request, &[self.sent as u8], 0).unwrap().serialize()))
+        }
+    }
+
+    #[test]
+    fn test_transport() {
+        let mut port = CountingTransport { sent: 0, broken: false };
+        let mut tn = 0;
+
+        assert_eq!(do_get(&mut port, &CONTROLLER, &DEVICE, 0, DMX_START_ADDRESS, &[], &mut tn, &mut Vec::new()), Ok(vec![1]));
+        assert_eq!(do_get(&mut port, &CONTROLLER, &DEVICE, 0, DMX_START_ADDRESS, &[], &mut tn, &mut Vec::new()), Ok(vec![2]));
+
+        port.broken = true;
+        assert!(matches!(do_get(&mut port, &CONTROLLER, &DEVICE, 0, DMX_START_ADDRESS, &[], &mut tn, &mut Vec::new()),
+            Err(RdmError::Transport(TransportError { kind: std::io::ErrorKind::BrokenPipe, .. }))));
+        assert!(matches!(do_discovery_algo(&mut port, &CONTROLLER, true, false), Err(RdmError::Transport(_))));
+
+        // Closures can borrow local state
+        let mut seen : Vec<u16> = Vec::new();
+        let mut closure = |data: &[u8]| -> Option<Vec<u8>> {
+            seen.push(Pkt::deserialize(data.to_vec()).ok()?.pid);
+            None
+        };
+        assert_eq!(do_get(&mut closure, &CONTROLLER, &DEVICE, 0, DEVICE_INFO, &[], &mut tn, &mut Vec::new()), Err(RdmError::NoResponse));
+        assert_eq!(seen, vec![DEVICE_INFO]);
+    }
+
+    #[test]
+    fn test_check_response() {
+        let request = RdmRequest::get(DEVICE, DMX_START_ADDRESS).source(CONTROLLER).tn(7).build().unwrap();
+        let check = |response: Pkt| check_response(&request, &PktRef::new(&response.serialize()).unwrap());
+
+        assert_eq!(check(Pkt::ack(&request, &[0x00, 0x01], 0).unwrap()), Ok(()));
+
+        let mut wrong_tn = Pkt::ack(&request, &[0x00, 0x01], 0).unwrap();
+        wrong_tn.tn = 8;
+        wrong_tn.set_checksum();
+        assert_eq!(check(wrong_tn), Err(RdmError::UnexpectedResponse));
+
+        let mut wrong_pid = Pkt::ack(&request, &[0x00, 0x01], 0).unwrap();
+        wrong_pid.pid = DEVICE_INFO;
+        wrong_pid.set_checksum();
+        assert_eq!(check(wrong_pid), Err(RdmError::UnexpectedResponse));
+
+        let set = RdmRequest::set(DEVICE, DMX_START_ADDRESS).source(CONTROLLER).tn(7).build().unwrap();
+        assert_eq!(check(Pkt::ack(&set, &[], 0).unwrap()), Err(RdmError::UnexpectedResponse));
+
+        // do_get holds a direct response to the same check
+        let mut tn = 0;
+        let mut wrong_cc = |data: &[u8]| -> Option<Vec<u8>> {
+            let request = Pkt::deserialize(data.to_vec()).ok()?;
+            let set = RdmRequest::set(request.destination, request.pid).source(request.source).tn(request.tn).build().ok()?;
+            Some(Pkt::ack(&set, &[0x00, 0x01], 0).ok()?.serialize())
+        };
+        assert_eq!(do_get(&mut wrong_cc, &CONTROLLER, &DEVICE, 0, DMX_START_ADDRESS, &[], &mut tn, &mut Vec::new()), Err(RdmError::UnexpectedResponse));
+    }
+
+    /// Answers every other poll with Pending, the way a real async port would while the frame
+    /// is on the wire, and never finishes sleeping so timeouts can be tested.
+    struct SlowAsyncTransport {
+        responder: fn(&[u8]) -> Option<Vec<u8>>
+    }
+
+    struct YieldOnce(bool);
+
+    impl std::future::Future for YieldOnce {
+        type Output = ();
+
+        fn poll(mut self: std::pin::Pin<&mut Self>, cx: &mut std::task::Context<'_>) -> std::task::Poll<()> {
+            if self.0 {
+                return std::task::Poll::Ready(());
+            }
+            self.0 = true;
+            cx.waker().wake_by_ref();
+            std::task::Poll::Pending
+        }
+    }
+
+    impl AsyncRdmTransport for SlowAsyncTransport {
+        async fn send_request(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>, TransportError> {
+            YieldOnce(false).await;
+            Ok((self.responder)(data))
+        }
+
+        async fn send_broadcast(&mut self, _data: &[u8]) -> Result<(), TransportError> {
+            YieldOnce(false).await;
+            Ok(())
+        }
+
+        async fn sleep(&mut self, _duration: Duration) {
+            std::future::pending::<()>().await
+        }
+    }
+
+    #[test]
+    fn test_async() {
+        let mut port_a = SlowAsyncTransport { responder: overflow_responder };
+        let mut port_b = SlowAsyncTransport { responder: overflow_responder };
+        let (mut tn_a, mut tn_b) = (0, 0);
+
+        let (a, b) = block_on(async {
+            let a = do_get_async(&mut port_a, &CONTROLLER, &DEVICE, 0, SUPPORTED_PARAMETERS, &[], &mut tn_a, &mut Vec::new()).await;
+            let b = do_set_async(&mut port_b, &CONTROLLER, &DEVICE, 0, DEVICE_LABEL, b"x", &mut tn_b, &mut Vec::new()).await;
+            (a, b)
+        });
+        assert_eq!(a, Ok(overflow_payload()));
+        assert_eq!(b, Err(RdmError::Nack(NackReason::WriteProtect)));
+
+        // The deadline wins against an ACK_TIMER wait that never ends
+        let mut port = SlowAsyncTransport { responder: ack_timer_responder };
+        let mut tn = 0;
+        let result = block_on(timeout(YieldOnce(false), do_set_async(&mut port, &CONTROLLER, &SLOW_DEVICE, 0, RESET_DEVICE, &[], &mut tn, &mut Vec::new())));
+        assert_eq!(result, Err(Elapsed));
+
+        let mut port = SlowAsyncTransport { responder: overflow_responder };
+        let mut tn = 0;
+        let result = block_on(timeout(std::future::pending(), do_get_async(&mut port, &CONTROLLER, &DEVICE, 0, SUPPORTED_PARAMETERS, &[], &mut tn, &mut Vec::new())));
+        assert_eq!(result, Ok(Ok(overflow_payload())));
+    }
+
+    #[test]
+    fn test_overflow_reassembly() {
+        let mut tn = 0;
+        let pd = do_get(&mut overflow_responder, &CONTROLLER, &DEVICE, 0, SUPPORTED_PARAMETERS, &[], &mut tn, &mut Vec::new()).unwrap();
+
+        assert_eq!(pd, overflow_payload());
+        assert_eq!(tn, 3);
+    }
+
+    #[test]
+    fn test_overflow_errors() {
+        let mut tn = 0;
+        assert_eq!(do_get(&mut overflow_responder, &CONTROLLER, &DEVICE, 0, PROXIED_DEVICES, &[], &mut tn, &mut Vec::new()),
+            Err(RdmError::NackDuringOverflow { reason: NackReason::HardwareFault, received: 228 }));
+
+        let mut tn = 0;
+        assert_eq!(do_set(&mut overflow_responder, &CONTROLLER, &DEVICE, 0, DEVICE_LABEL, b"label", &mut tn, &mut Vec::new()),
+            Err(RdmError::Nack(NackReason::WriteProtect)));
+
+        let mut tn = 0;
+        assert_eq!(do_get(&mut overflow_responder, &CONTROLLER, &DEVICE, 0, SLOT_INFO, &[], &mut tn, &mut Vec::new()),
+            Err(RdmError::OverflowLimit(MAX_OVERFLOW_LENGTH)));
+
+        let mut tn = 0;
+        assert_eq!(do_get(&mut overflow_responder, &CONTROLLER, &DEVICE, 0, DEVICE_INFO, &[], &mut tn, &mut Vec::new()),
+            Err(RdmError::UnexpectedResponse));
+
+        let mut tn = 0;
+        assert_eq!(do_get(&mut overflow_responder, &CONTROLLER, &DEVICE, 0, SENSOR_VALUE, &[0], &mut tn, &mut Vec::new()),
+            Err(RdmError::NoResponse));
+    }
+
+    #[test]
+    fn test_controller() {
+        let mut line = sim::Line::new();
+        line.add(sim::Responder::new(DEVICE).parameter(DEVICE_LABEL, b"Truss 1").parameter(SENSOR_VALUE, &[0, 0x00, 0x2A, 0x00, 0x10, 0x00, 0x40, 0x00, 0x00]));
+        line.add(sim::Responder::new(SLOW_DEVICE).parameter(DEVICE_LABEL, b"Truss 2"));
+
+        let mut controller = RdmController::new(line, CONTROLLER);
+
+        let info = controller.get::<DeviceInfoPD>(&DEVICE, 0).unwrap();
+        assert_eq!(info.rdm_protocol_version, 0x0100);
+        assert_eq!(controller.get::<DeviceLabelPD>(&DEVICE, 0).unwrap().device_label, "Truss 1");
+        assert_eq!(controller.tn(), 2);
+
+        let sensor = controller.get_with::<SensorValuePD>(&DEVICE, 0, &0).unwrap();
+        assert_eq!((sensor.present, sensor.lowest, sensor.highest), (42, 16, 64));
+
+        controller.set::<DeviceLabelPD>(&DEVICE, 0, &DeviceLabelPD { device_label: "Truss 3".to_string() }).unwrap();
+        assert_eq!(controller.get::<DeviceLabelPD>(&DEVICE, 0).unwrap().device_label, "Truss 3");
+
+        assert!(matches!(controller.get::<DeviceLabelPD>(&DEVICE, 1), Err(RdmError::Nack(NackReason::SubDeviceOutOfRange))));
+        assert_eq!(controller.get_raw(&DEVICE, 0, SLOT_INFO, &[]), Err(RdmError::Nack(NackReason::UnknownPid)));
+
+        let requests = controller.transport().requests();
+        controller.broadcast_set::<DeviceLabelPD>(0, &DeviceLabelPD { device_label: "Rig".to_string() }).unwrap();
+        assert_eq!(controller.transport().requests(), requests + 1);
+        assert_eq!(controller.transport().responder(&SLOW_DEVICE).unwrap().get(DEVICE_LABEL), Some(&b"Rig"[..]));
+
+        // The message count of the last response says whether QUEUED_MESSAGE has anything
+        let queued = |data: &[u8]| -> Option<Vec<u8>> {
+            let request = Pkt::deserialize(data.to_vec()).ok()?;
+            Some(Pkt::ack(&request, &[], 4).ok()?.serialize())
+        };
+        let mut queued_controller = RdmController::new(queued, CONTROLLER);
+        assert_eq!(queued_controller.message_count(), 0);
+        queued_controller.get_raw(&DEVICE, 0, DMX_START_ADDRESS, &[]).unwrap();
+        assert_eq!(queued_controller.message_count(), 4);
+
+        let line = controller.into_transport();
+        let mut controller = RdmController::new(line, CONTROLLER);
+        assert_eq!(block_on(controller.get_async::<DeviceLabelPD>(&DEVICE, 0)).unwrap().device_label, "Rig");
+    }
+
+    #[test]
+    fn test_controller_vendorcast() {
+        let other_vendor = Uid::new(0x646F, 0x00000001);
+        let mut line = sim::Line::new();
+        line.add(sim::Responder::new(DEVICE).parameter(DEVICE_LABEL, b""));
+        line.add(sim::Responder::new(SLOW_DEVICE).parameter(DEVICE_LABEL, b""));
+        line.add(sim::Responder::new(other_vendor).parameter(DEVICE_LABEL, b""));
+        line.add(sim::Responder::new(Uid::new(0x1234, 0x00000003)).profile(sim::Profile::Dead));
+        let tod : Vec<Uid> = line.responders().iter().map(|responder| responder.uid).collect();
+
+        let mut controller = RdmController::new(line, CONTROLLER);
+        let label = DeviceLabelPD { device_label: "Vendor X".to_string() };
+
+        // Nothing is waited for but the bus idle
+        let before = controller.transport().now();
+        block_on(controller.vendorcast_set_async::<DeviceLabelPD>(0x1234, 0, &label)).unwrap();
+        assert_eq!(controller.transport().now() - before, BROADCAST_IDLE);
+        assert_eq!(controller.transport().responder(&SLOW_DEVICE).unwrap().get(DEVICE_LABEL), Some(&b"Vendor X"[..]));
+        assert_eq!(controller.transport().responder(&other_vendor).unwrap().get(DEVICE_LABEL), Some(&b""[..]));
+
+        controller.set::<DeviceLabelPD>(&SLOW_DEVICE, 0, &DeviceLabelPD { device_label: "Changed".to_string() }).unwrap();
+
+        let verification = controller.verify::<DeviceLabelPD>(&Uid::vendorcast(0x1234), &tod, 0, &label).unwrap();
+        assert_eq!(verification.confirmed, vec![DEVICE]);
+        assert_eq!(verification.mismatched, vec![SLOW_DEVICE]);
+        assert_eq!(verification.failed, vec![(Uid::new(0x1234, 0x00000003), RdmError::NoResponse)]);
+        assert!(!verification.is_confirmed());
+
+        let requests = controller.transport().requests();
+        assert_eq!(controller.broadcast_set_raw(&DEVICE, 0, DEVICE_LABEL, b"Oops"), Err(RdmError::NotBroadcast(DEVICE)));
+        assert_eq!(controller.transport().requests(), requests);
+
+        controller.broadcast_get_raw(&Uid::BROADCAST, 0, DEVICE_LABEL, &[]).unwrap();
+        assert_eq!(controller.transport().requests(), requests + 1);
+    }
+
+    #[test]
+    fn test_controller_discards_stray_frames() {
+        // The first answer is a late response to an old TN, the second is line noise
+        let mut sent = 0;
+        let transport = move |data: &[u8]| -> Option<Vec<u8>> {
+            let mut request = Pkt::deserialize(data.to_vec()).ok()?;
+            sent += 1;
+            match sent {
+                1 => {
+                    request.tn = request.tn.wrapping_sub(1);
+                    Some(Pkt::ack(&request, &[0x00, 0x01], 0).ok()?.serialize())
+                },
+                2 => Some(vec![0xCC, 0x01, 0x02]),
+                _ => Some(Pkt::ack(&request, &[0x00, 0x2A], 0).ok()?.serialize())
+            }
+        };
+
+        let mut controller = RdmController::new(transport, CONTROLLER).retries(2);
+        assert_eq!(controller.get_raw(&DEVICE, 0, DMX_START_ADDRESS, &[]), Ok(vec![0x00, 0x2A]));
+        assert_eq!(controller.tn(), 3);
+
+        // One retry rides out a single stray
+        let mut line = sim::Line::new();
+        line.add(sim::Responder::new(DEVICE));
+        let mut sent = 0;
+        let transport = move |data: &[u8]| -> Option<Vec<u8>> {
+            sent += 1;
+            let mut response = Pkt::deserialize(line.transact(data)?).ok()?;
+            if sent == 1 {
+                response.tn = response.tn.wrapping_sub(1);
+            }
+            Some(response.serialize())
+        };
+        let mut controller = RdmController::new(transport, CONTROLLER).retries(1);
+        assert!(controller.get::<DeviceInfoPD>(&DEVICE, 0).is_ok());
+        assert_eq!(controller.tn(), 2);
+
+        // Only the frame that went unanswered is sent again: a SET the device has answered
+        // with ACK_TIMER is not repeated because a poll went missing
+        let mut sent : Vec<u16> = Vec::new();
+        let transport = |data: &[u8]| -> Option<Vec<u8>> {
+            let request = Pkt::deserialize(data.to_vec()).ok()?;
+            sent.push(request.pid);
+            let response = match (request.pid, sent.len()) {
+                (RESET_DEVICE, _) => Pkt::ack_timer(&request, Duration::ZERO, 0),
+                (QUEUED_MESSAGE, 2) => return None,
+                (QUEUED_MESSAGE, _) => PktBuilder::new(CommandClass::SetCommandResponse, request.source, RESET_DEVICE)
+                    .source(request.destination)
+                    .tn(request.tn)
+                    .response_type(ResponseType::Ack)
+                    .build(),
+                _ => return None
+            };
+            Some(response.ok()?.serialize())
+        };
+        let mut controller = RdmController::new(transport, CONTROLLER).retries(2);
+        assert_eq!(controller.set_raw(&DEVICE, 0, RESET_DEVICE, &[0x01]), Ok(vec![]));
+        drop(controller);
+        assert_eq!(sent, vec![RESET_DEVICE, QUEUED_MESSAGE, QUEUED_MESSAGE]);
+
+        // and a stray part way through ACK_OVERFLOW keeps the chunks received so far
+        let mut sent = 0;
+        let transport = move |data: &[u8]| -> Option<Vec<u8>> {
+            let mut request = Pkt::deserialize(data.to_vec()).ok()?;
+            sent += 1;
+            match sent {
+                1 => Some(Pkt::ack_overflow(&request, &[0x01, 0x02], 0).ok()?.serialize()),
+                2 => {
+                    request.tn = request.tn.wrapping_sub(1);
+                    Some(Pkt::ack_overflow(&request, &[0x01, 0x02], 0).ok()?.serialize())
+                },
+                _ => Some(Pkt::ack(&request, &[0x03], 0).ok()?.serialize())
+            }
+        };
+        let mut controller = RdmController::new(transport, CONTROLLER).retries(1);
+        assert_eq!(controller.get_raw(&DEVICE, 0, SUPPORTED_PARAMETERS, &[]), Ok(vec![0x01, 0x02, 0x03]));
+        assert_eq!(controller.tn(), 3);
+
+        // A request that cannot be framed is never sent
+        let mut line = sim::Line::new();
+        line.add(sim::Responder::new(DEVICE));
+        let mut controller = RdmController::new(line, CONTROLLER).retries(3);
+        assert_eq!(controller.set_raw(&DEVICE, 0, DEVICE_LABEL, &[0x41; RDM_MAX_PDL + 1]), Err(RdmError::PdTooLong(RDM_MAX_PDL + 1)));
+        assert_eq!(controller.transport().requests(), 0);
+
+        // Nor is a label past its max_len, rather than storing the first 32 characters
+        let label = DeviceLabelPD { device_label: "x".repeat(40) };
+        assert_eq!(controller.set::<DeviceLabelPD>(&DEVICE, 0, &label), Err(RdmError::FieldTooLong { len: 40, max_len: 32 }));
+        assert_eq!(controller.transport().requests(), 0);
+
+        // Out of retries, the last stray is the error
+        let mut line = sim::Line::new();
+        line.add(sim::Responder::new(DEVICE).profile(sim::Profile::WrongTn));
+        let mut controller = RdmController::new(line, CONTROLLER).retries(1);
+        assert_eq!(controller.get::<DeviceInfoPD>(&DEVICE, 0).map(|_| ()), Err(RdmError::UnexpectedResponse));
+        assert_eq!(controller.transport().requests(), 2);
+
+        // Or the timeout, if that comes first
+        let mut line = sim::Line::new();
+        line.add(sim::Responder::new(DEVICE).profile(sim::Profile::Dead));
+        let mut controller = RdmController::new(line, CONTROLLER).retries(10).timeout(Duration::ZERO);
+        assert_eq!(controller.get::<DeviceInfoPD>(&DEVICE, 0).map(|_| ()), Err(RdmError::RequestTimeout(Duration::ZERO)));
+        assert_eq!(controller.transport().requests(), 1);
+    }
+}
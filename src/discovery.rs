@@ -0,0 +1,1008 @@
+//! DISC_UNIQUE_BRANCH tree walk
+//! The algorithm is written once against AsyncRdmTransport; the blocking entry points run it
+//! over a BlockingTransport.
+
+use std::sync::mpsc;
+use std::time::Instant;
+
+use crate::*;
+
+/// Counters reported when a discovery run finishes
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiscoveryStats {
+    pub branches_probed: usize,
+    pub devices_found: usize,
+    pub devices_confirmed: usize,
+    pub mute_failures: usize,
+    pub collisions: usize,
+    pub unresolved: usize,
+    pub proxied_devices: usize,
+    pub elapsed: Duration
+}
+
+/// Progress reported while discovery runs
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiscoveryEvent {
+    /// A DISC_UNIQUE_BRANCH for range came back with result
+    BranchProbed { range: UidRange, result: DiscoveryResponse },
+    /// A device answered DISC_MUTE and is in the table
+    DeviceFound(Uid),
+    /// A device from the previous table still answers DISC_MUTE
+    DeviceConfirmed(Uid),
+    /// A device was heard, or was expected, but did not answer DISC_MUTE
+    MuteFailed(Uid),
+    /// More than one device answered in range, so it will be split
+    Collision { range: UidRange },
+    /// A single UID kept colliding, or kept ignoring DISC_MUTE, until its budget ran out
+    UnresolvableCollision(Uid),
+    /// A managed proxy listed uid in PROXIED_DEVICES
+    ProxiedDeviceFound { uid: Uid, proxy: Uid },
+    /// The run is over
+    Finished(DiscoveryStats)
+}
+
+/// Receives DiscoveryEvents as they happen, e.g. to drive a progress bar
+/// Implemented for closures, for mpsc::Sender so events can be consumed on another thread, and
+/// for () when nobody is listening.
+pub trait DiscoveryObserver {
+    fn on_event(&mut self, event: DiscoveryEvent);
+}
+
+impl DiscoveryObserver for () {
+    fn on_event(&mut self, _event: DiscoveryEvent) {}
+}
+
+impl<F> DiscoveryObserver for F where F: FnMut(DiscoveryEvent) {
+    fn on_event(&mut self, event: DiscoveryEvent) {
+        self(event)
+    }
+}
+
+impl DiscoveryObserver for mpsc::Sender<DiscoveryEvent> {
+    fn on_event(&mut self, event: DiscoveryEvent) {
+        // A receiver that has gone away should not stop discovery
+        let _ = self.send(event);
+    }
+}
+
+/// Forwards events to the observer and keeps the counters for Finished
+struct Progress<'a, O: DiscoveryObserver + ?Sized> {
+    observer: &'a mut O,
+    stats: DiscoveryStats,
+    started: Instant
+}
+
+impl<'a, O: DiscoveryObserver + ?Sized> Progress<'a, O> {
+    fn new(observer: &'a mut O) -> Progress<'a, O> {
+        Progress { observer, stats: DiscoveryStats::default(), started: Instant::now() }
+    }
+
+    fn emit(&mut self, event: DiscoveryEvent) {
+        match event {
+            DiscoveryEvent::BranchProbed { .. } => self.stats.branches_probed += 1,
+            DiscoveryEvent::DeviceFound(_) => self.stats.devices_found += 1,
+            DiscoveryEvent::DeviceConfirmed(_) => self.stats.devices_confirmed += 1,
+            DiscoveryEvent::MuteFailed(_) => self.stats.mute_failures += 1,
+            DiscoveryEvent::Collision { .. } => self.stats.collisions += 1,
+            DiscoveryEvent::UnresolvableCollision(_) => self.stats.unresolved += 1,
+            DiscoveryEvent::ProxiedDeviceFound { .. } => self.stats.proxied_devices += 1,
+            DiscoveryEvent::Finished(_) => {}
+        }
+
+        self.observer.on_event(event);
+    }
+
+    fn finish(&mut self) {
+        self.stats.elapsed = self.started.elapsed();
+        self.emit(DiscoveryEvent::Finished(self.stats));
+    }
+
+    fn check_budget(&self, config: &DiscoveryConfig) -> Result<(), RdmError> {
+        match config.time_budget {
+            Some(budget) if self.started.elapsed() > budget => Err(RdmError::DiscoveryTimeout(budget)),
+            _ => Ok(())
+        }
+    }
+}
+
+/// Retry and timing policy for discovery.
+/// The default matches do_discovery_algo(.., true, false).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DiscoveryConfig {
+    /// DISC_UN_MUTE broadcasts sent before the walk.  There is no reply to check, so retrying
+    /// an un-mute just means sending it more than once; 0 skips it.
+    pub unmute_broadcasts: u8,
+    /// Extra DISC_UNIQUE_BRANCH rounds for a branch that is down to one UID and still collides
+    pub dub_retries: u8,
+    /// Probe a branch that came back empty once more before giving up on it, or up to
+    /// dub_retries more times if it is half of a collision that otherwise came up empty.  This
+    /// doubles the cost of every empty branch, so it is only worth it on lines that lose DUB
+    /// responses.
+    pub retry_empty_branches: bool,
+    /// Extra DISC_MUTE attempts before a device counts as not muting
+    pub mute_retries: u8,
+    /// Wait between a failed attempt and its retry
+    pub retry_backoff: Duration,
+    /// Give up with DiscoveryTimeout once the run has taken this long
+    pub time_budget: Option<Duration>,
+    /// After the walk, ask every managed proxy for its PROXIED_DEVICES and add them to the result
+    pub query_proxies: bool,
+    /// UIDs to walk, e.g. UidRange::manufacturer to find only one vendor's fixtures
+    pub range: UidRange
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        DiscoveryConfig {
+            unmute_broadcasts: 1,
+            dub_retries: 2,
+            retry_empty_branches: false,
+            mute_retries: 0,
+            retry_backoff: Duration::ZERO,
+            time_budget: None,
+            query_proxies: true,
+            range: UidRange::ALL
+        }
+    }
+}
+
+impl DiscoveryConfig {
+    /// The policy behind the do_mute and repeat_disc flags of do_discovery_algo
+    fn from_flags(do_mute: bool, repeat_disc: bool) -> DiscoveryConfig {
+        DiscoveryConfig {
+            unmute_broadcasts: do_mute as u8,
+            retry_empty_branches: repeat_disc,
+            ..DiscoveryConfig::default()
+        }
+    }
+}
+
+async fn do_discovery_node<T: AsyncRdmTransport + ?Sized>(transport: &mut T, my_uid: &Uid, range: &UidRange, tn: &mut u8) -> Result<DiscoveryResponse, RdmError> {
+
+    *tn = tn.overflowing_add(1).0;
+
+    let output_pkt = RdmRequest::discovery(Uid::BROADCAST, DISC_UNIQUE_BRANCH)
+        .source(*my_uid)
+        .tn(*tn)
+        .pd(&range.serialize())
+        .build()
+        .expect("DISC_UNIQUE_BRANCH parameter data is always 12 bytes");
+
+    let mut buf = [0u8; RDM_MAX_FRAME_LENGTH];
+    let len = output_pkt.encode_into(&mut buf)?;
+
+    let data = match transport.send_request(&buf[..len]).await? {
+        None => return Ok(DiscoveryResponse::None), // No response means no response
+        Some(data) => data
+    };
+
+    match DiscUniqueBranchResponse::decode(&data) {
+        Ok(device_uid) => Ok(DiscoveryResponse::One(device_uid)),
+        Err(DubError::NoData) => Ok(DiscoveryResponse::None),
+        Err(e) => {
+            debug!("do_discovery_node: {}", e);
+            Ok(DiscoveryResponse::Some) // We got something, but don't know what it is.
+        }
+    }
+}
+
+/// Runs the discovery algorithm.
+/// 0. Optionally: Unmute all (out of scope)
+/// 1. Do allcall discovery.   If no response, then return empty Vec If response, go to 2
+/// 2. Split any range that collides into non-overlapping halves and walk those, lower half first
+/// 3. Return the UIDs in the order they were muted, which is sorted
+///
+/// repeat_disc probes every empty branch once more; see DiscoveryConfig for finer control.
+///
+/// Fails if the transport does, or with UnresolvableCollision if one UID never resolves to a
+/// muted device; devices that do not answer are simply not in the result.
+pub fn do_discovery_algo<T: RdmTransport + ?Sized>(transport: &mut T, my_uid: &Uid, do_mute: bool, repeat_disc: bool) -> Result<Vec<Uid>, RdmError> {
+    do_discovery_algo_observed(transport, my_uid, do_mute, repeat_disc, &mut ())
+}
+
+/// do_discovery_algo, reporting progress to observer as it goes
+pub fn do_discovery_algo_observed<T: RdmTransport + ?Sized, O: DiscoveryObserver + ?Sized>(transport: &mut T, my_uid: &Uid, do_mute: bool, repeat_disc: bool, observer: &mut O) -> Result<Vec<Uid>, RdmError> {
+    block_on(do_discovery_algo_observed_async(&mut BlockingTransport(transport), my_uid, do_mute, repeat_disc, observer))
+}
+
+/// Async version of do_discovery_algo
+pub async fn do_discovery_algo_async<T: AsyncRdmTransport + ?Sized>(transport: &mut T, my_uid: &Uid, do_mute: bool, repeat_disc: bool) -> Result<Vec<Uid>, RdmError> {
+    do_discovery_algo_observed_async(transport, my_uid, do_mute, repeat_disc, &mut ()).await
+}
+
+/// Async version of do_discovery_algo_observed
+pub async fn do_discovery_algo_observed_async<T: AsyncRdmTransport + ?Sized, O: DiscoveryObserver + ?Sized>(transport: &mut T, my_uid: &Uid, do_mute: bool, repeat_disc: bool, observer: &mut O) -> Result<Vec<Uid>, RdmError> {
+    do_discovery_configured_async(transport, my_uid, &DiscoveryConfig::from_flags(do_mute, repeat_disc), observer).await
+        .map(|result| result.tod())
+}
+
+/// Runs the discovery algorithm with the retry and timing policy in config, reporting progress
+/// to observer as it goes.  Keeps each device's DISC_MUTE response alongside its UID.
+pub fn do_discovery_configured<T: RdmTransport + ?Sized, O: DiscoveryObserver + ?Sized>(transport: &mut T, my_uid: &Uid, config: &DiscoveryConfig, observer: &mut O) -> Result<DiscoveryResult, RdmError> {
+    block_on(do_discovery_configured_async(&mut BlockingTransport(transport), my_uid, config, observer))
+}
+
+/// Async version of do_discovery_configured
+pub async fn do_discovery_configured_async<T: AsyncRdmTransport + ?Sized, O: DiscoveryObserver + ?Sized>(transport: &mut T, my_uid: &Uid, config: &DiscoveryConfig, observer: &mut O) -> Result<DiscoveryResult, RdmError> {
+
+    let mut tn : u8 = 0;
+    let mut progress = Progress::new(observer);
+
+    for attempt in 0..config.unmute_broadcasts {
+        if attempt > 0 {
+            transport.sleep(config.retry_backoff).await;
+        }
+
+        tn = tn.overflowing_add(1).0;
+
+        let output_pkt = RdmRequest::discovery(Uid::BROADCAST, DISC_UN_MUTE)
+            .source(*my_uid)
+            .tn(tn)
+            .build()
+            .expect("DISC_UN_MUTE has no parameter data");
+
+        let mut buf = [0u8; RDM_MAX_FRAME_LENGTH];
+        let len = output_pkt.encode_into(&mut buf)?;
+
+        transport.send_broadcast(&buf[..len]).await?; // send global unmute
+
+    }
+
+    let result = async {
+        let mut devices = do_discovery_walk(transport, &mut progress, config, my_uid, config.range, &mut tn).await?;
+
+        if config.query_proxies {
+            fetch_proxied_devices(transport, &mut progress, config, my_uid, &mut devices, &mut tn).await?;
+        }
+
+        Ok(DiscoveryResult { devices })
+    }.await;
+
+    progress.finish();
+
+    result
+}
+
+/// PROXIED_DEVICE_COUNT polls allowed while a proxy's list keeps changing under us
+const MAX_PROXY_LIST_POLLS : u8 = 3;
+
+/// Adds the devices behind every managed proxy in devices, tagged with the proxy, keeping
+/// devices sorted.  A proxy that will not give up its list is logged and skipped; only a
+/// transport failure or the time budget ends the run.
+async fn fetch_proxied_devices<T: AsyncRdmTransport + ?Sized, O: DiscoveryObserver + ?Sized>(transport: &mut T, progress: &mut Progress<'_, O>, config: &DiscoveryConfig, my_uid: &Uid, devices: &mut Vec<DiscoveredDevice>, tn: &mut u8) -> Result<(), RdmError> {
+    let proxies : Vec<Uid> = devices.iter().filter(|device| device.mute.managed_proxy()).map(|device| device.uid).collect();
+
+    for proxy in proxies {
+        progress.check_budget(config)?;
+
+        let proxied = match get_proxied_devices(transport, my_uid, &proxy, tn).await {
+            Ok(proxied) => proxied,
+            Err(RdmError::Transport(e)) => return Err(RdmError::Transport(e)),
+            Err(e) => {
+                warn!("fetch_proxied_devices: could not get PROXIED_DEVICES from {}: {}", proxy, e);
+                continue;
+            }
+        };
+
+        for uid in proxied {
+            if devices.iter().any(|device| device.uid == uid) {
+                continue; // answered the walk itself
+            }
+
+            progress.emit(DiscoveryEvent::ProxiedDeviceFound { uid, proxy });
+            devices.push(DiscoveredDevice {
+                uid,
+                mute: MuteResponse { control_field: CONTROL_FIELD_PROXIED_DEVICE, binding_uid: None },
+                proxy: Some(proxy)
+            });
+        }
+    }
+
+    devices.sort_by_key(|device| device.uid);
+
+    Ok(())
+}
+
+/// Reads a proxy's list of devices.
+/// Reading PROXIED_DEVICES clears the list change flag, so if it is set again afterwards the list
+/// changed while it was being read and is read again.
+async fn get_proxied_devices<T: AsyncRdmTransport + ?Sized>(transport: &mut T, my_uid: &Uid, proxy: &Uid, tn: &mut u8) -> Result<Vec<Uid>, RdmError> {
+    let mut uids : Vec<Uid> = Vec::new();
+
+    for poll in 0..MAX_PROXY_LIST_POLLS {
+        let count = ProxiedDeviceCountPD::deserialize(do_get_async(transport, my_uid, proxy, 0, PROXIED_DEVICE_COUNT, &[], tn, &mut Vec::new()).await?)?;
+
+        if poll > 0 && !count.list_change {
+            return Ok(uids);
+        }
+
+        if count.device_count == 0 {
+            return Ok(Vec::new());
+        }
+
+        uids = ProxiedDevicesPD::deserialize(do_get_async(transport, my_uid, proxy, 0, PROXIED_DEVICES, &[], tn, &mut Vec::new()).await?)?.uids;
+    }
+
+    debug!("get_proxied_devices: {} is still changing its list, keeping the last one read", proxy);
+
+    Ok(uids)
+}
+
+/// A device found by discovery, and what it said when it was muted
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiscoveredDevice {
+    pub uid: Uid,
+    /// For a proxied device this is not a real response, just CONTROL_FIELD_PROXIED_DEVICE
+    pub mute: MuteResponse,
+    /// The managed proxy that listed this device, if it did not answer the walk itself
+    pub proxy: Option<Uid>
+}
+
+/// Everything a discovery run found, in UID order
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct DiscoveryResult {
+    pub devices: Vec<DiscoveredDevice>
+}
+
+impl DiscoveryResult {
+    /// Just the UIDs
+    pub fn tod(&self) -> Vec<Uid> {
+        self.devices.iter().map(|device| device.uid).collect()
+    }
+
+    pub fn get(&self, uid: &Uid) -> Option<&DiscoveredDevice> {
+        self.devices.iter().find(|device| device.uid == *uid)
+    }
+
+    /// Devices whose children have to be fetched with PROXIED_DEVICES
+    pub fn proxies(&self) -> impl Iterator<Item = &DiscoveredDevice> {
+        self.devices.iter().filter(|device| device.mute.managed_proxy())
+    }
+
+    /// The devices listed by proxy
+    pub fn proxied_by<'a>(&'a self, proxy: &'a Uid) -> impl Iterator<Item = &'a DiscoveredDevice> {
+        self.devices.iter().filter(move |device| device.proxy.as_ref() == Some(proxy))
+    }
+
+    /// The ports of a multi-port device, which all report the same binding UID
+    pub fn bound_to<'a>(&'a self, binding_uid: &'a Uid) -> impl Iterator<Item = &'a DiscoveredDevice> {
+        self.devices.iter().filter(move |device| device.mute.binding_uid.as_ref() == Some(binding_uid))
+    }
+}
+
+/// Result of an incremental discovery, relative to the table of devices it was given
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiscoveryDiff {
+    /// Found by the tree walk and not in the previous table
+    pub added: Vec<Uid>,
+    /// In the previous table but did not answer DISC_MUTE, DISC_UNIQUE_BRANCH or a second
+    /// DISC_MUTE after the walk, and no proxy listed it
+    pub removed: Vec<Uid>,
+    /// In the previous table and still answering
+    pub unchanged: Vec<Uid>
+}
+
+impl DiscoveryDiff {
+    /// The updated table of devices, sorted
+    pub fn tod(&self) -> Vec<Uid> {
+        let mut tod : Vec<Uid> = self.unchanged.iter().chain(self.added.iter()).copied().collect();
+        tod.sort();
+        tod
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+}
+
+/// Updates a table of devices without starting over.
+/// 1. DISC_MUTE every device in previous.  The ones that ACK are still there, and are now muted.
+/// 2. Walk the tree.  Only unmuted devices answer, so this finds newcomers and anything that was
+///    power cycled, without re-finding the rest of the rig.
+///
+/// Nothing is un-muted, so previous should come from a full do_discovery_algo on this line.
+pub fn do_incremental_discovery<T: RdmTransport + ?Sized>(transport: &mut T, my_uid: &Uid, previous: &[Uid]) -> Result<DiscoveryDiff, RdmError> {
+    do_incremental_discovery_observed(transport, my_uid, previous, &mut ())
+}
+
+/// do_incremental_discovery, reporting progress to observer as it goes.
+/// Known devices that answer DISC_MUTE are reported as DeviceConfirmed, ones that are not found
+/// at all as MuteFailed.
+pub fn do_incremental_discovery_observed<T: RdmTransport + ?Sized, O: DiscoveryObserver + ?Sized>(transport: &mut T, my_uid: &Uid, previous: &[Uid], observer: &mut O) -> Result<DiscoveryDiff, RdmError> {
+    block_on(do_incremental_discovery_observed_async(&mut BlockingTransport(transport), my_uid, previous, observer))
+}
+
+/// Async version of do_incremental_discovery
+pub async fn do_incremental_discovery_async<T: AsyncRdmTransport + ?Sized>(transport: &mut T, my_uid: &Uid, previous: &[Uid]) -> Result<DiscoveryDiff, RdmError> {
+    do_incremental_discovery_observed_async(transport, my_uid, previous, &mut ()).await
+}
+
+/// Async version of do_incremental_discovery_observed
+pub async fn do_incremental_discovery_observed_async<T: AsyncRdmTransport + ?Sized, O: DiscoveryObserver + ?Sized>(transport: &mut T, my_uid: &Uid, previous: &[Uid], observer: &mut O) -> Result<DiscoveryDiff, RdmError> {
+    do_incremental_discovery_configured_async(transport, my_uid, previous, &DiscoveryConfig::default(), observer).await
+}
+
+/// do_incremental_discovery with the retry and timing policy in config.
+/// Nothing is un-muted, so config.unmute_broadcasts is ignored.  Every previous UID is checked,
+/// but only config.range is walked for new ones.  With config.query_proxies every managed proxy,
+/// known or new, has its list of devices read again.
+pub fn do_incremental_discovery_configured<T: RdmTransport + ?Sized, O: DiscoveryObserver + ?Sized>(transport: &mut T, my_uid: &Uid, previous: &[Uid], config: &DiscoveryConfig, observer: &mut O) -> Result<DiscoveryDiff, RdmError> {
+    block_on(do_incremental_discovery_configured_async(&mut BlockingTransport(transport), my_uid, previous, config, observer))
+}
+
+/// Async version of do_incremental_discovery_configured
+pub async fn do_incremental_discovery_configured_async<T: AsyncRdmTransport + ?Sized, O: DiscoveryObserver + ?Sized>(transport: &mut T, my_uid: &Uid, previous: &[Uid], config: &DiscoveryConfig, observer: &mut O) -> Result<DiscoveryDiff, RdmError> {
+    let mut tn : u8 = 0;
+    let mut progress = Progress::new(observer);
+
+    let devices : Result<Vec<DiscoveredDevice>, RdmError> = async {
+        let mut devices : Vec<DiscoveredDevice> = Vec::new();
+
+        for uid in previous {
+            progress.check_budget(config)?;
+
+            if let Some(mute) = mute_with_retries(transport, config, my_uid, uid, &mut tn).await? {
+                progress.emit(DiscoveryEvent::DeviceConfirmed(*uid));
+                devices.push(DiscoveredDevice { uid: *uid, mute, proxy: None });
+            }
+        }
+
+        for device in do_discovery_walk(transport, &mut progress, config, my_uid, config.range, &mut tn).await? {
+            // A known device that missed the mute but answered the tree walk is still there
+            if !devices.iter().any(|known| known.uid == device.uid) {
+                devices.push(device);
+            }
+        }
+
+        // A device whose mute ACK was lost is muted but silent, so it gets one more chance to
+        // answer before it counts as gone
+        for uid in previous {
+            if devices.iter().any(|device| device.uid == *uid) {
+                continue;
+            }
+
+            progress.check_budget(config)?;
+
+            if let Some(mute) = send_mute_message(transport, my_uid, uid, &mut tn).await? {
+                progress.emit(DiscoveryEvent::DeviceConfirmed(*uid));
+                devices.push(DiscoveredDevice { uid: *uid, mute, proxy: None });
+            }
+        }
+
+        if config.query_proxies {
+            fetch_proxied_devices(transport, &mut progress, config, my_uid, &mut devices, &mut tn).await?;
+        }
+
+        for uid in previous.iter().filter(|uid| !devices.iter().any(|device| device.uid == **uid)) {
+            debug!("do_incremental_discovery: {} did not answer DISC_MUTE", uid);
+            progress.emit(DiscoveryEvent::MuteFailed(*uid));
+        }
+
+        Ok(devices)
+    }.await;
+
+    progress.finish();
+
+    let devices = devices?;
+    let mut diff = DiscoveryDiff::default();
+
+    for uid in previous {
+        if devices.iter().any(|device| device.uid == *uid) {
+            diff.unchanged.push(*uid);
+        } else {
+            diff.removed.push(*uid);
+        }
+    }
+
+    diff.added = devices.iter().map(|device| device.uid).filter(|uid| !previous.contains(uid)).collect();
+
+    Ok(diff)
+}
+
+/// send_mute_message, tried config.mute_retries more times before giving up on the device
+async fn mute_with_retries<T: AsyncRdmTransport + ?Sized>(transport: &mut T, config: &DiscoveryConfig, my_uid: &Uid, uid: &Uid, tn : &mut u8) -> Result<Option<MuteResponse>, RdmError> {
+    for attempt in 0..=config.mute_retries {
+        if attempt > 0 {
+            debug!("mute_with_retries: {} did not answer, retry {}", uid, attempt);
+            transport.sleep(config.retry_backoff).await;
+        }
+
+        if let Some(response) = send_mute_message(transport, my_uid, uid, tn).await? {
+            return Ok(Some(response));
+        }
+    }
+
+    Ok(None)
+}
+
+/// Sends a mute message and then returns what the device said if it got an ACK, or None if it didn't
+async fn send_mute_message<T: AsyncRdmTransport + ?Sized>(transport: &mut T, my_uid: &Uid, uid: &Uid, tn : &mut u8) -> Result<Option<MuteResponse>, RdmError> {
+    *tn = tn.overflowing_add(1).0;
+
+    let output_pkt = RdmRequest::discovery(*uid, DISC_MUTE)
+        .source(*my_uid)
+        .tn(*tn)
+        .build()
+        .expect("DISC_MUTE has no parameter data");
+
+    let mut buf = [0u8; RDM_MAX_FRAME_LENGTH];
+    let len = output_pkt.encode_into(&mut buf)?;
+
+    let data = match transport.send_request(&buf[..len]).await? {
+        None => return Ok(None),
+        Some(data) => data
+    };
+
+    // PktRef validates the checksum, so a decode error is as good as no response.
+    let data_pkt = match PktRef::new(&data) {
+        Err(e) => {
+            debug!("send_mute_message: bad response from {}: {}",uid,e);
+            return Ok(None);
+        },
+        Ok(data_pkt) => data_pkt
+    };
+
+    if data_pkt.port_or_response_type() != RESPONSE_TYPE_ACK 
+        || data_pkt.source() != *uid 
+        || data_pkt.cc() != DISCOVERY_COMMAND_RESPONSE 
+        || data_pkt.pid() != DISC_MUTE {
+        return Ok(None);
+    }
+
+    // The ACK is what mutes it; a device that leaves out the control field is still muted.
+    match MuteResponse::deserialize(data_pkt.pd().to_vec()) {
+        Ok(response) => Ok(Some(response)),
+        Err(e) => {
+            debug!("send_mute_message: {} sent a bad control field: {}",uid,e);
+            Ok(Some(MuteResponse::default()))
+        }
+    }
+}
+
+/// Halving the 48 bit UID space reaches a single UID after at most 48 splits
+const MAX_DISCOVERY_DEPTH : u8 = 48;
+
+/// A range waiting on the discovery stack
+#[derive(Clone, Copy)]
+struct Branch {
+    range: UidRange,
+    depth: u8,
+    attempts: u8,
+    /// The lower half of a collision, walked just before its upper half
+    lower_half: bool,
+    /// This is the upper half of a collision whose lower half came back empty, so something
+    /// should answer here
+    sibling_empty: bool
+}
+
+impl Branch {
+    fn new(range: UidRange, depth: u8) -> Branch {
+        Branch { range, depth, attempts: 0, lower_half: false, sibling_empty: false }
+    }
+
+    /// Probes an empty result gets before the branch is given up on.  One extra probe catches
+    /// most lost responses; only an empty half of a collision whose other half was empty too
+    /// is worth the full dub_retries, since the collision says something is there.
+    fn empty_retries(&self, config: &DiscoveryConfig) -> u8 {
+        match (config.retry_empty_branches, self.sibling_empty) {
+            (false, _) => 0,
+            (true, false) => 1,
+            (true, true) => config.dub_retries.max(1)
+        }
+    }
+}
+
+/// Walks range with an explicit stack, splitting it into non-overlapping halves wherever more
+/// than one device answers, or the one that answers will not mute.
+/// A range that is down to one UID and still will not resolve gets config.dub_retries more
+/// rounds and is then reported as UnresolvableCollision.  The rest of the range is still walked, so
+/// everything else ends up muted and the observer sees every device that was found.
+async fn do_discovery_walk<T: AsyncRdmTransport + ?Sized, O: DiscoveryObserver + ?Sized>(transport: &mut T, progress: &mut Progress<'_, O>, config: &DiscoveryConfig, my_uid: &Uid, range: UidRange, tn : &mut u8) -> Result<Vec<DiscoveredDevice>, RdmError> {
+    let mut tod : Vec<DiscoveredDevice> = Vec::new();
+    let mut unresolved : Option<Uid> = None;
+    let mut stack = vec![Branch::new(range, 0)];
+
+    while let Some(branch) = stack.pop() {
+        progress.check_budget(config)?;
+
+        if branch.attempts > 0 {
+            transport.sleep(config.retry_backoff).await;
+        }
+
+        debug!("do_discovery_walk({})", branch.range);
+
+        let result = do_discovery_node(transport, my_uid, &branch.range, tn).await?;
+
+        progress.emit(DiscoveryEvent::BranchProbed { range: branch.range, result });
+
+        match result {
+            DiscoveryResponse::None => {
+                if branch.attempts < branch.empty_retries(config) {
+                    stack.push(Branch { attempts: branch.attempts + 1, ..branch });
+                } else if branch.lower_half {
+                    // The upper half is next on the stack
+                    if let Some(upper) = stack.last_mut() {
+                        upper.sibling_empty = true;
+                    }
+                }
+                continue; // nothing in this branch
+            },
+            DiscoveryResponse::One(found_uid) => {
+                debug!("do_discovery_walk: Found {}, muting it.",found_uid);
+                if let Some(mute) = mute_with_retries(transport,config,my_uid,&found_uid,tn).await? {
+                    progress.emit(DiscoveryEvent::DeviceFound(found_uid));
+                    tod.push(DiscoveredDevice { uid: found_uid, mute, proxy: None });
+                    continue;
+                }
+                progress.emit(DiscoveryEvent::MuteFailed(found_uid));
+            },
+            DiscoveryResponse::Some => {
+                progress.emit(DiscoveryEvent::Collision { range: branch.range });
+            }
+        }
+
+        // Something is still answering in this range, so dig deeper.
+        match branch.range.split_half() {
+            Some((lower, upper)) if branch.depth < MAX_DISCOVERY_DEPTH => {
+                // Upper goes on first so the lower half is walked first and the TOD comes out sorted
+                stack.push(Branch::new(upper, branch.depth + 1));
+                stack.push(Branch { lower_half: true, ..Branch::new(lower, branch.depth + 1) });
+            },
+            _ if branch.attempts < config.dub_retries => {
+                stack.push(Branch { attempts: branch.attempts + 1, ..branch });
+            },
+            _ => {
+                warn!("do_discovery_walk: unresolvable collision at {}", branch.range.min);
+                progress.emit(DiscoveryEvent::UnresolvableCollision(branch.range.min));
+                unresolved.get_or_insert(branch.range.min);
+            }
+        }
+    }
+
+    match unresolved {
+        Some(uid) => Err(RdmError::UnresolvableCollision(uid)),
+        None => Ok(tod)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTROLLER : Uid = Uid { mfg: 0x044E, dev: 0x00000001 };
+    const DEVICES : [Uid; 3] = [Uid { mfg: 0x3638, dev: 0x08101AD8 }, Uid { mfg: 0x646F, dev: 0x000E8E22 }, Uid { mfg: 0x646F, dev: 0x000FB190 }];
+
+    /// A line of responders that remember being muted
+    struct Line {
+        devices: Vec<Uid>,
+        muted: Vec<Uid>,
+        /// Answer DISC_UNIQUE_BRANCH but never DISC_MUTE
+        deaf: Vec<Uid>,
+        /// Each entry loses the next response to that PID
+        drops: Vec<u16>,
+        /// DISC_MUTE parameter data by device; empty if not listed
+        mute_pd: Vec<(Uid, Vec<u8>)>,
+        /// PROXIED_DEVICES by proxy, and whether its list change flag is set
+        proxied: Vec<(Uid, Vec<Uid>, bool)>,
+        /// Bytes of PROXIED_DEVICES already sent as ACK_OVERFLOW
+        overflow_sent: usize,
+        sent: Vec<u16>
+    }
+
+    impl Line {
+        fn new(devices: &[Uid]) -> Line {
+            Line { devices: devices.to_vec(), muted: Vec::new(), deaf: Vec::new(), drops: Vec::new(), mute_pd: Vec::new(), proxied: Vec::new(), overflow_sent: 0, sent: Vec::new() }
+        }
+
+        fn respond(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+            let request = Pkt::deserialize(data.to_vec()).ok()?;
+            self.sent.push(request.pid);
+
+            if let Some(index) = self.drops.iter().position(|pid| *pid == request.pid) {
+                // The device still acts on it, only the reply is lost
+                self.drops.remove(index);
+                if request.pid == DISC_MUTE {
+                    self.muted.push(request.destination);
+                }
+                return None;
+            }
+
+            match request.pid {
+                DISC_UNIQUE_BRANCH => {
+                    let range = UidRange::from_bytes(&request.pd).ok()?;
+                    let found : Vec<&Uid> = self.devices.iter()
+                        .filter(|uid| range.contains(uid) && !self.muted.contains(uid))
+                        .collect();
+
+                    match found.len() {
+                        0 => None,
+                        1 => Some(DiscUniqueBranchResponse::encode(found[0]).to_vec()),
+                        _ => Some(vec![0xFE; 24])
+                    }
+                },
+                DISC_MUTE if self.devices.contains(&request.destination) && !self.deaf.contains(&request.destination) => {
+                    self.muted.push(request.destination);
+                    let pd = self.mute_pd.iter().find(|(uid, _)| *uid == request.destination).map(|(_, pd)| pd.clone()).unwrap_or_default();
+                    Some(Pkt::ack(&request, &pd, 0).ok()?.serialize())
+                },
+                DISC_UN_MUTE => {
+                    self.muted.clear();
+                    None
+                },
+                PROXIED_DEVICE_COUNT => {
+                    let (_, list, list_change) = self.proxied.iter().find(|(proxy, _, _)| *proxy == request.destination)?;
+                    let pd = ProxiedDeviceCountPD { device_count: list.len() as u16, list_change: *list_change }.serialize().ok()?;
+                    Some(Pkt::ack(&request, &pd, 0).ok()?.serialize())
+                },
+                PROXIED_DEVICES => {
+                    let entry = self.proxied.iter_mut().find(|(proxy, _, _)| *proxy == request.destination)?;
+                    let pd = ProxiedDevicesPD { uids: entry.1.clone() }.serialize().ok()?;
+                    let chunk = &pd[self.overflow_sent..pd.len().min(self.overflow_sent + 228)];
+
+                    if self.overflow_sent + chunk.len() < pd.len() {
+                        self.overflow_sent += chunk.len();
+                        Some(Pkt::ack_overflow(&request, chunk, 0).ok()?.serialize())
+                    } else {
+                        self.overflow_sent = 0;
+                        entry.2 = false;
+                        Some(Pkt::ack(&request, chunk, 0).ok()?.serialize())
+                    }
+                },
+                _ => None
+            }
+        }
+    }
+
+    impl RdmTransport for Line {
+        fn send_request(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>, TransportError> {
+            Ok(self.respond(data))
+        }
+    }
+
+    impl AsyncRdmTransport for Line {
+        async fn send_request(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>, TransportError> {
+            Ok(self.respond(data))
+        }
+
+        async fn send_broadcast(&mut self, data: &[u8]) -> Result<(), TransportError> {
+            self.respond(data);
+            Ok(())
+        }
+
+        async fn sleep(&mut self, _duration: Duration) {}
+    }
+
+    #[test]
+    fn test_incremental_discovery() {
+        let gone = Uid::new(0x646F, 0x00000001);
+        let previous = vec![DEVICES[0], gone, DEVICES[2]];
+
+        let mut line = Line::new(&DEVICES);
+        let diff = do_incremental_discovery(&mut line, &CONTROLLER, &previous).unwrap();
+
+        assert_eq!(diff.added, vec![DEVICES[1]]);
+        assert_eq!(diff.removed, vec![gone]);
+        assert_eq!(diff.unchanged, vec![DEVICES[0], DEVICES[2]]);
+        assert_eq!(diff.tod(), DEVICES.to_vec());
+        assert!(!diff.is_empty());
+
+        // Only the DISC_MUTE sweep and one empty DUB should be on the wire
+        let mut line = Line::new(&DEVICES);
+        let diff = do_incremental_discovery(&mut line, &CONTROLLER, &DEVICES).unwrap();
+        assert!(diff.is_empty());
+        assert_eq!(line.sent, vec![DISC_MUTE, DISC_MUTE, DISC_MUTE, DISC_UNIQUE_BRANCH]);
+
+        // A lost mute ACK leaves the device muted and out of the walk, so it is asked again
+        let mut line = Line::new(&DEVICES);
+        line.drops.push(DISC_MUTE);
+        let diff = do_incremental_discovery(&mut line, &CONTROLLER, &DEVICES).unwrap();
+        assert!(diff.is_empty());
+        assert_eq!(diff.unchanged, DEVICES.to_vec());
+        assert_eq!(line.sent, vec![DISC_MUTE, DISC_MUTE, DISC_MUTE, DISC_UNIQUE_BRANCH, DISC_MUTE]);
+
+        // A known proxy has its devices read again
+        let proxy = DEVICES[0];
+        let behind = vec![Uid::new(0x7A70, 1), Uid::new(0x7A70, 2)];
+        let mut line = Line::new(&DEVICES);
+        line.mute_pd.push((proxy, MuteResponse { control_field: CONTROL_FIELD_MANAGED_PROXY, binding_uid: None }.serialize()));
+        line.proxied.push((proxy, behind.clone(), false));
+        let previous = [DEVICES[0], DEVICES[1], DEVICES[2], behind[0], Uid::new(0x7A70, 3)];
+        let diff = do_incremental_discovery(&mut line, &CONTROLLER, &previous).unwrap();
+        assert_eq!(diff.added, vec![behind[1]]);
+        assert_eq!(diff.removed, vec![Uid::new(0x7A70, 3)]);
+        assert_eq!(diff.unchanged, previous[..4].to_vec());
+    }
+
+    #[test]
+    fn test_discovery_events() {
+        let mut events : Vec<DiscoveryEvent> = Vec::new();
+        let found = do_discovery_algo_observed(&mut Line::new(&DEVICES), &CONTROLLER, true, false, &mut |event| events.push(event)).unwrap();
+
+        let found_events : Vec<Uid> = events.iter().filter_map(|event| match event {
+            DiscoveryEvent::DeviceFound(uid) => Some(*uid),
+            _ => None
+        }).collect();
+        assert_eq!(found_events, found);
+
+        assert!(matches!(events[0], DiscoveryEvent::BranchProbed { result: DiscoveryResponse::Some, .. }));
+        assert!(matches!(events[1], DiscoveryEvent::Collision { .. }));
+
+        let stats = match events.last() {
+            Some(DiscoveryEvent::Finished(stats)) => *stats,
+            _ => panic!("last event should be Finished")
+        };
+        assert_eq!(stats.devices_found, 3);
+        assert_eq!(stats.mute_failures, 0);
+        assert_eq!(stats.branches_probed, events.iter().filter(|event| matches!(event, DiscoveryEvent::BranchProbed { .. })).count());
+
+        // Events can go to another thread over a channel
+        let (mut sender, receiver) = mpsc::channel();
+        let previous = [DEVICES[0], Uid::new(0x646F, 0x00000001)];
+        do_incremental_discovery_observed(&mut Line::new(&DEVICES), &CONTROLLER, &previous, &mut sender).unwrap();
+        drop(sender);
+
+        let events : Vec<DiscoveryEvent> = receiver.iter().collect();
+        assert_eq!(events[0], DiscoveryEvent::DeviceConfirmed(DEVICES[0]));
+        assert_eq!(events[events.len() - 2], DiscoveryEvent::MuteFailed(Uid::new(0x646F, 0x00000001)));
+        assert!(matches!(events.last(), Some(DiscoveryEvent::Finished(DiscoveryStats { devices_found: 2, devices_confirmed: 1, mute_failures: 1, .. }))));
+    }
+
+    #[test]
+    fn test_discovery_ranges_do_not_overlap() {
+        let mut probed : Vec<UidRange> = Vec::new();
+        do_discovery_algo_observed(&mut Line::new(&DEVICES), &CONTROLLER, true, false, &mut |event| {
+            if let DiscoveryEvent::BranchProbed { range, .. } = event {
+                probed.push(range);
+            }
+        }).unwrap();
+
+        // Any two probes are either nested or disjoint, so no midpoint is probed from both sides
+        for a in &probed {
+            for b in &probed {
+                let overlap = a.intersect(b);
+                assert!(overlap.is_none() || overlap == Some(*a) || overlap == Some(*b));
+            }
+        }
+    }
+
+    #[test]
+    fn test_discovery_unresolvable_collision() {
+        // A fixture that answers DUB but never accepts DISC_MUTE used to recurse forever
+        let mut line = Line::new(&DEVICES);
+        line.deaf.push(DEVICES[1]);
+
+        let mut found : Vec<Uid> = Vec::new();
+        let result = do_discovery_algo_observed(&mut line, &CONTROLLER, true, false, &mut |event| {
+            if let DiscoveryEvent::DeviceFound(uid) = event {
+                found.push(uid);
+            }
+        });
+
+        assert_eq!(result, Err(RdmError::UnresolvableCollision(DEVICES[1])));
+        assert_eq!(found, vec![DEVICES[0], DEVICES[2]]);
+        assert_eq!(line.muted, vec![DEVICES[0], DEVICES[2]]);
+
+        // Two devices sharing a UID garble every DUB down to the single UID range
+        let mut line = Line::new(&[DEVICES[0], DEVICES[2], DEVICES[2]]);
+        let result = do_discovery_algo(&mut line, &CONTROLLER, true, true);
+        assert_eq!(result, Err(RdmError::UnresolvableCollision(DEVICES[2])));
+        assert!(line.sent.iter().filter(|pid| **pid == DISC_UNIQUE_BRANCH).count() < 200);
+    }
+
+    #[test]
+    fn test_discovery_config() {
+        // A lost DUB response hides the whole line unless empty branches are retried
+        let mut line = Line::new(&DEVICES);
+        line.drops.push(DISC_UNIQUE_BRANCH);
+        assert_eq!(do_discovery_configured(&mut line, &CONTROLLER, &DiscoveryConfig::default(), &mut ()).unwrap().tod(), Vec::new());
+
+        let config = DiscoveryConfig { retry_empty_branches: true, ..DiscoveryConfig::default() };
+        let mut line = Line::new(&DEVICES);
+        line.drops.push(DISC_UNIQUE_BRANCH);
+        assert_eq!(do_discovery_configured(&mut line, &CONTROLLER, &config, &mut ()).unwrap().tod(), DEVICES.to_vec());
+
+        // An empty line costs one extra probe, however many dub_retries there are
+        let config = DiscoveryConfig { retry_empty_branches: true, dub_retries: 5, ..DiscoveryConfig::default() };
+        let mut line = Line::new(&[]);
+        assert_eq!(do_discovery_configured(&mut line, &CONTROLLER, &config, &mut ()).unwrap().tod(), Vec::new());
+        assert_eq!(line.sent.iter().filter(|pid| **pid == DISC_UNIQUE_BRANCH).count(), 2);
+
+        // A lost mute ACK is retried rather than split around
+        let config = DiscoveryConfig { mute_retries: 1, unmute_broadcasts: 3, ..DiscoveryConfig::default() };
+        let mut line = Line::new(&DEVICES[..1]);
+        line.drops.push(DISC_MUTE);
+        assert_eq!(do_discovery_configured(&mut line, &CONTROLLER, &config, &mut ()).unwrap().tod(), DEVICES[..1].to_vec());
+        assert_eq!(line.sent, vec![DISC_UN_MUTE, DISC_UN_MUTE, DISC_UN_MUTE, DISC_UNIQUE_BRANCH, DISC_MUTE, DISC_MUTE]);
+
+        // Retrying a device that never mutes runs out the time budget
+        let config = DiscoveryConfig { retry_backoff: Duration::from_millis(5), time_budget: Some(Duration::from_millis(1)), ..DiscoveryConfig::default() };
+        let mut line = Line::new(&DEVICES);
+        line.deaf.push(DEVICES[0]);
+        assert_eq!(do_discovery_configured(&mut line, &CONTROLLER, &config, &mut ()), Err(RdmError::DiscoveryTimeout(Duration::from_millis(1))));
+    }
+
+    #[test]
+    fn test_discovery_range() {
+        // Only one vendor's fixtures on a mixed line
+        let config = DiscoveryConfig { range: UidRange::manufacturer(0x646F), ..DiscoveryConfig::default() };
+        let mut line = Line::new(&DEVICES);
+        let mut probed : Vec<UidRange> = Vec::new();
+        let result = do_discovery_configured(&mut line, &CONTROLLER, &config, &mut |event| {
+            if let DiscoveryEvent::BranchProbed { range, .. } = event {
+                probed.push(range);
+            }
+        }).unwrap();
+
+        assert_eq!(result.tod(), DEVICES[1..].to_vec());
+        assert_eq!(line.muted, DEVICES[1..].to_vec());
+        assert_eq!(probed[0], config.range);
+        assert!(probed.iter().all(|range| config.range.intersect(range) == Some(*range)));
+
+        // Previously known devices outside the range are still checked
+        let diff = do_incremental_discovery_configured(&mut Line::new(&DEVICES), &CONTROLLER, &DEVICES[..1], &config, &mut ()).unwrap();
+        assert_eq!(diff.unchanged, DEVICES[..1].to_vec());
+        assert_eq!(diff.added, DEVICES[1..].to_vec());
+    }
+
+    #[test]
+    fn test_discovery_mute_responses() {
+        let binding = Uid::new(0x646F, 0x000E8E00);
+        let mut line = Line::new(&DEVICES);
+        line.mute_pd.push((DEVICES[0], vec![0x00, 0x05]));
+        line.mute_pd.push((DEVICES[1], MuteResponse { control_field: CONTROL_FIELD_SUB_DEVICE, binding_uid: Some(binding) }.serialize()));
+        line.mute_pd.push((DEVICES[2], MuteResponse { control_field: 0, binding_uid: Some(binding) }.serialize()));
+
+        let result = do_discovery_configured(&mut line, &CONTROLLER, &DiscoveryConfig::default(), &mut ()).unwrap();
+        assert_eq!(result.tod(), DEVICES.to_vec());
+
+        let proxy = result.get(&DEVICES[0]).unwrap().mute;
+        assert!(proxy.managed_proxy() && proxy.boot_loader());
+        assert!(!proxy.sub_devices() && !proxy.proxied_device());
+        assert_eq!(proxy.binding_uid, None);
+        assert_eq!(result.proxies().map(|device| device.uid).collect::<Vec<Uid>>(), vec![DEVICES[0]]);
+
+        assert!(result.get(&DEVICES[1]).unwrap().mute.sub_devices());
+        assert_eq!(result.bound_to(&binding).map(|device| device.uid).collect::<Vec<Uid>>(), vec![DEVICES[1], DEVICES[2]]);
+    }
+
+    #[test]
+    fn test_discovery_proxied_devices() {
+        let proxy = DEVICES[0];
+        // More than fits in one frame, so the list comes back as ACK_OVERFLOW
+        let mut behind : Vec<Uid> = (0..40).map(|dev| Uid::new(0x7A70, dev)).collect();
+        behind.push(DEVICES[2]);
+
+        let mut line = Line::new(&DEVICES);
+        line.mute_pd.push((proxy, MuteResponse { control_field: CONTROL_FIELD_MANAGED_PROXY, binding_uid: None }.serialize()));
+        line.proxied.push((proxy, behind.clone(), true));
+
+        let mut events : Vec<DiscoveryEvent> = Vec::new();
+        let result = do_discovery_configured(&mut line, &CONTROLLER, &DiscoveryConfig::default(), &mut |event| events.push(event)).unwrap();
+
+        assert_eq!(result.devices.len(), 43);
+        assert!(result.devices.windows(2).all(|pair| pair[0].uid < pair[1].uid));
+        assert_eq!(result.proxied_by(&proxy).map(|device| device.uid).collect::<Vec<Uid>>(), behind[..40].to_vec());
+        assert_eq!(result.get(&DEVICES[2]).unwrap().proxy, None);
+        assert!(result.get(&behind[0]).unwrap().mute.proxied_device());
+
+        let proxy_traffic : Vec<u16> = line.sent.iter().copied().filter(|pid| *pid == PROXIED_DEVICE_COUNT || *pid == PROXIED_DEVICES).collect();
+        assert_eq!(proxy_traffic, vec![PROXIED_DEVICE_COUNT, PROXIED_DEVICES, PROXIED_DEVICES, PROXIED_DEVICE_COUNT]);
+        assert!(matches!(events.last(), Some(DiscoveryEvent::Finished(DiscoveryStats { devices_found: 3, proxied_devices: 40, .. }))));
+
+        // Turned off, only the walk is done
+        let mut line = Line::new(&DEVICES);
+        line.mute_pd.push((proxy, MuteResponse { control_field: CONTROL_FIELD_MANAGED_PROXY, binding_uid: None }.serialize()));
+        line.proxied.push((proxy, behind, true));
+        let config = DiscoveryConfig { query_proxies: false, ..DiscoveryConfig::default() };
+        assert_eq!(do_discovery_configured(&mut line, &CONTROLLER, &config, &mut ()).unwrap().tod(), DEVICES.to_vec());
+    }
+
+    #[test]
+    fn test_discovery_sync_and_async() {
+        let found = do_discovery_algo(&mut Line::new(&DEVICES), &CONTROLLER, true, false).unwrap();
+        assert_eq!(found, DEVICES.to_vec());
+
+        let found = block_on(do_discovery_algo_async(&mut Line::new(&DEVICES), &CONTROLLER, true, false)).unwrap();
+        assert_eq!(found, DEVICES.to_vec());
+    }
+}
@@ -0,0 +1,166 @@
+//! DISC_UNIQUE_BRANCH response encoding
+//! The response has no RDM framing: up to seven 0xFE preamble bytes, an 0xAA separator, then the
+//! UID and its checksum with every byte sent twice, once ORed with 0xAA and once with 0x55.
+//! Shared by the controller's discovery and anything that answers DUB, such as a responder or a
+//! simulated line.
+
+use std::fmt;
+
+use crate::*;
+
+const DUB_PREAMBLE : u8 = 0xFE;
+const DUB_SEPARATOR : u8 = 0xAA;
+
+/// Longest preamble a responder may send
+pub const DUB_MAX_PREAMBLE : usize = 7;
+
+/// Length of the encoded UID and checksum after the separator
+const DUB_EUID_LENGTH : usize = 16;
+
+/// Length of a response with the full preamble
+pub const DUB_RESPONSE_LENGTH : usize = DUB_MAX_PREAMBLE + 1 + DUB_EUID_LENGTH;
+
+/// Why a DISC_UNIQUE_BRANCH response did not decode to a UID
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DubError {
+    /// Nothing was received, so nothing is in the branch
+    NoData,
+    /// Something was received but it is not a response: a bad preamble or separator, a truncated
+    /// response, or byte pairs that do not carry the 0xAA / 0x55 pattern.  Usually more than one
+    /// device answering at once.
+    Collision,
+    /// Well formed, but the checksum does not match the UID.  Devices answering at once are
+    /// wired-AND on the line, which tends to end up here.
+    ChecksumMismatch { expected: u16, received: u16 }
+}
+
+impl fmt::Display for DubError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DubError::NoData => write!(f, "no DISC_UNIQUE_BRANCH response"),
+            DubError::Collision => write!(f, "garbled DISC_UNIQUE_BRANCH response"),
+            DubError::ChecksumMismatch { expected, received } => write!(f, "DISC_UNIQUE_BRANCH checksum mismatch: expected {:04X}, received {:04X}", expected, received),
+        }
+    }
+}
+
+impl std::error::Error for DubError {}
+
+/// Encoder and decoder for the response to DISC_UNIQUE_BRANCH
+pub struct DiscUniqueBranchResponse;
+
+impl DiscUniqueBranchResponse {
+    /// The response uid sends, with the full seven byte preamble
+    pub fn encode(uid: &Uid) -> [u8; DUB_RESPONSE_LENGTH] {
+        let mut buffer = [DUB_PREAMBLE; DUB_RESPONSE_LENGTH];
+        buffer[DUB_MAX_PREAMBLE] = DUB_SEPARATOR;
+
+        let euid = &mut buffer[DUB_MAX_PREAMBLE + 1..];
+
+        for (i, byte) in uid.uid_serialize().iter().enumerate() {
+            euid[i * 2] = byte | 0xAA;
+            euid[i * 2 + 1] = byte | 0x55;
+        }
+
+        let checksum = rdm_checksum(&euid[0..12]);
+
+        for (i, byte) in checksum.to_be_bytes().iter().enumerate() {
+            euid[12 + i * 2] = byte | 0xAA;
+            euid[12 + i * 2 + 1] = byte | 0x55;
+        }
+
+        buffer
+    }
+
+    /// Decodes a response with 0 to 7 preamble bytes.  Anything after the checksum is ignored.
+    pub fn decode(data: &[u8]) -> Result<Uid, DubError> {
+        if data.is_empty() {
+            return Err(DubError::NoData);
+        }
+
+        let preamble = data.iter().take_while(|byte| **byte == DUB_PREAMBLE).count();
+
+        if preamble > DUB_MAX_PREAMBLE || data.get(preamble) != Some(&DUB_SEPARATOR) {
+            return Err(DubError::Collision);
+        }
+
+        let euid = data.get(preamble + 1..preamble + 1 + DUB_EUID_LENGTH).ok_or(DubError::Collision)?;
+
+        // Every pair has to carry the bits the encoding sets, or this is not one device talking
+        let mut decoded = [0u8; DUB_EUID_LENGTH / 2];
+
+        for (i, pair) in euid.chunks_exact(2).enumerate() {
+            if pair[0] & 0xAA != 0xAA || pair[1] & 0x55 != 0x55 {
+                return Err(DubError::Collision);
+            }
+            decoded[i] = pair[0] & pair[1];
+        }
+
+        let expected = rdm_checksum(&euid[0..12]);
+        let received = u16::from_be_bytes([decoded[6], decoded[7]]);
+
+        if expected != received {
+            return Err(DubError::ChecksumMismatch { expected, received });
+        }
+
+        Ok(Uid::from_array(decoded[0..6].try_into().unwrap()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UID : Uid = Uid { mfg: 0x646F, dev: 0x000E8E22 };
+
+    #[test]
+    fn test_dub_round_trip() {
+        let encoded = DiscUniqueBranchResponse::encode(&UID);
+        assert_eq!(encoded.len(), 24);
+        assert_eq!(encoded[0..8], [0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0xFE, 0xAA]);
+        assert_eq!(encoded[8..10], [0x646F_u16.to_be_bytes()[0] | 0xAA, 0x646F_u16.to_be_bytes()[0] | 0x55]);
+
+        // Any preamble from none to seven bytes
+        for preamble in 0..=DUB_MAX_PREAMBLE {
+            assert_eq!(DiscUniqueBranchResponse::decode(&encoded[DUB_MAX_PREAMBLE - preamble..]), Ok(UID));
+        }
+
+        let mut trailing = encoded.to_vec();
+        trailing.extend([0x00, 0xFF]);
+        assert_eq!(DiscUniqueBranchResponse::decode(&trailing), Ok(UID));
+    }
+
+    #[test]
+    fn test_dub_errors() {
+        let encoded = DiscUniqueBranchResponse::encode(&UID);
+
+        assert_eq!(DiscUniqueBranchResponse::decode(&[]), Err(DubError::NoData));
+
+        // Missing separator
+        let mut missing = encoded.to_vec();
+        missing.remove(DUB_MAX_PREAMBLE);
+        assert_eq!(DiscUniqueBranchResponse::decode(&missing), Err(DubError::Collision));
+
+        // Eight preamble bytes
+        let mut long = vec![0xFE];
+        long.extend(encoded);
+        assert_eq!(DiscUniqueBranchResponse::decode(&long), Err(DubError::Collision));
+
+        // Truncated
+        assert_eq!(DiscUniqueBranchResponse::decode(&encoded[..20]), Err(DubError::Collision));
+
+        // Pair without the 0x55 bits
+        let mut broken = encoded;
+        broken[9] = 0x00;
+        assert_eq!(DiscUniqueBranchResponse::decode(&broken), Err(DubError::Collision));
+
+        // Two devices answering at once are wired-AND together
+        let other = DiscUniqueBranchResponse::encode(&Uid::new(0x3638, 0x08101AD8));
+        let collided : Vec<u8> = encoded.iter().zip(other.iter()).map(|(a, b)| a & b).collect();
+        assert!(matches!(DiscUniqueBranchResponse::decode(&collided), Err(DubError::ChecksumMismatch { .. })));
+
+        let mut bad_checksum = encoded;
+        bad_checksum[8] ^= 0x01;
+        assert!(matches!(DiscUniqueBranchResponse::decode(&bad_checksum), Err(DubError::ChecksumMismatch { .. })));
+    }
+}
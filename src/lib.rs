@@ -1,22 +1,137 @@
 #![allow(dead_code)]
-#![feature(int_abs_diff)]
 #[macro_use]
 extern crate log;
 extern crate simplelog;
+// Lets #[derive(RdmParameter)] name this crate as e1_20 from inside it too
+extern crate self as e1_20;
 
 use std::fmt;
-use core::cmp::Ordering;
 use core::cmp::min;
 use std::str;
-use std::u32;
-use std::u16;
+use std::time::Duration;
 
 use serde::{Deserialize, Serialize};
 
+mod controller;
+mod discovery;
+mod dub;
+mod transport;
+
+pub mod pd;
+pub mod sim;
+
+pub use controller::*;
+pub use discovery::*;
+pub use dub::*;
+pub use pd::PdField;
+pub use transport::*;
+
+pub use e1_20_derive::RdmParameter;
+
+/// The traits behind the parameter data structs, whose serialize and deserialize come from
+/// PdCodec: use e1_20::prelude::*;
+pub mod prelude {
+    pub use crate::{ParameterData, PdCodec, PdField, RdmParameter};
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_uid_parse() {
+        let uid = Uid::new(0x044E, 0x01234567);
+        assert_eq!("044E:01234567".parse(), Ok(uid));
+        assert_eq!("044e01234567".parse(), Ok(uid));
+        assert_eq!("0x044E01234567".parse(), Ok(uid));
+        assert_eq!(" 44E:1234567 ".parse(), Ok(uid));
+        assert_eq!(uid.to_string().parse(), Ok(uid));
+
+        assert_eq!("".parse::<Uid>(), Err(ParseUidError::BadLength(0)));
+        assert_eq!("044E".parse::<Uid>(), Err(ParseUidError::BadLength(4)));
+        assert_eq!("044E:012345678".parse::<Uid>(), Err(ParseUidError::BadLength(13)));
+        assert_eq!("044E:".parse::<Uid>(), Err(ParseUidError::BadLength(4)));
+        assert_eq!("044G:01234567".parse::<Uid>(), Err(ParseUidError::BadDigit('G')));
+        assert_eq!("044E:+1234567".parse::<Uid>(), Err(ParseUidError::BadDigit('+')));
+        assert_eq!("04:4E:01234567".parse::<Uid>(), Err(ParseUidError::BadDigit(':')));
+
+        #[allow(deprecated)]
+        let (good, garbage) = (Uid::from_string("044E:01234567".to_string()), Uid::from_string("44".to_string()));
+        assert_eq!((good, garbage), (uid, Uid::new(0, 0)));
+
+        assert_eq!(Uid::from_bytes(&uid.uid_serialize()), Ok(uid));
+        assert_eq!(Uid::from_bytes(&uid.uid_serialize()[..5]), Err(RdmError::TooShort { needed: 6, got: 5 }));
+    }
+
+    #[test]
+    fn test_uid_addressing() {
+        let uid = Uid::new(0x044E, 0x01234567);
+        assert!(Uid::BROADCAST.is_broadcast() && !Uid::BROADCAST.is_vendorcast());
+        assert!(Uid::vendorcast(0x044E).is_vendorcast() && !Uid::vendorcast(0x044E).is_broadcast());
+        assert!(!uid.is_broadcast() && !uid.is_vendorcast());
+
+        assert!(uid.addresses(&uid));
+        assert!(Uid::BROADCAST.addresses(&uid));
+        assert!(Uid::vendorcast(0x044E).addresses(&uid));
+        assert!(!Uid::vendorcast(0x044F).addresses(&uid));
+        assert!(!Uid::new(0x044E, 0x01234568).addresses(&uid));
+
+        let mut changed = uid;
+        changed.set_mfg(0x1234);
+        changed.set_dev(0x00000001);
+        assert_eq!(changed, Uid::new(0x1234, 0x00000001));
+        changed.set_from_64(uid.get_as_64());
+        assert_eq!(changed, uid);
+
+        let mut uids = vec![Uid::new(0x1234, 1), Uid::new(0x044E, 2), Uid::new(0x044E, 1)];
+        uids.sort();
+        assert_eq!(uids, vec![Uid::new(0x044E, 1), Uid::new(0x044E, 2), Uid::new(0x1234, 1)]);
+
+        let labels : std::collections::HashMap<Uid, &str> = [(uid, "Spot 1")].into_iter().collect();
+        assert_eq!(labels.get(&uid), Some(&"Spot 1"));
+    }
+
+    #[test]
+    fn test_uid_range_split_half() {
+        let (lower, upper) = UidRange::ALL.split_half().unwrap();
+        assert_eq!(lower, UidRange::new(Uid::new(0, 0), Uid::new(0x3FFF, 0xFFFF_FFFF)));
+        assert_eq!(upper, UidRange::new(Uid::new(0x4000, 0), Uid::new(0x7FFF, 0xFFFF_FFFF)));
+
+        let pair = UidRange::new(Uid::new(0x044E, 0xFFFF_FFFF), Uid::new(0x044F, 0));
+        assert_eq!(pair.split_half(), Some((UidRange::new(pair.min, pair.min), UidRange::new(pair.max, pair.max))));
+
+        let single = UidRange::single(Uid::new(0x044E, 1));
+        assert_eq!(single.split_half(), None);
+    }
+
+    #[test]
+    fn test_uid_range() {
+        let vendor = UidRange::manufacturer(0x044E);
+        assert!(vendor.contains(&Uid::new(0x044E, 0)));
+        assert!(vendor.contains(&Uid::new(0x044E, 0xFFFF_FFFE)));
+        assert!(!vendor.contains(&Uid::vendorcast(0x044E)));
+        assert!(!vendor.contains(&Uid::new(0x044F, 0)));
+        assert_eq!(vendor.len(), 0xFFFF_FFFF);
+        assert_eq!(UidRange::ALL.len(), 1 << 47);
+        assert_eq!(UidRange::single(Uid::new(1, 2)).len(), 1);
+
+        let empty = UidRange::new(Uid::new(0x044E, 2), Uid::new(0x044E, 1));
+        assert!(empty.is_empty());
+        assert_eq!(empty.len(), 0);
+        assert_eq!(empty.iter().count(), 0);
+
+        assert_eq!(UidRange::ALL.intersect(&vendor), Some(vendor));
+        assert_eq!(vendor.intersect(&UidRange::manufacturer(0x044F)), None);
+        let straddle = UidRange::new(Uid::new(0x044E, 0xFFFF_0000), Uid::new(0x044F, 5));
+        assert_eq!(straddle.intersect(&UidRange::manufacturer(0x044F)), Some(UidRange::new(Uid::new(0x044F, 0), Uid::new(0x044F, 5))));
+
+        let uids : Vec<Uid> = UidRange::new(Uid::new(0x044E, 0xFFFF_FFFE), Uid::new(0x044F, 1)).into_iter().collect();
+        assert_eq!(uids, vec![Uid::new(0x044E, 0xFFFF_FFFE), Uid::vendorcast(0x044E), Uid::new(0x044F, 0), Uid::new(0x044F, 1)]);
+
+        assert_eq!(UidRange::from_bytes(&straddle.serialize()), Ok(straddle));
+        assert_eq!(UidRange::from_bytes(&straddle.serialize()[..11]), Err(RdmError::TooShort { needed: 12, got: 11 }));
+    }
+
     #[test]
     fn it_works() {
         let result = 2 + 2;
@@ -40,7 +155,7 @@ mod tests {
     
         println!("{:?}",testpkt);
     
-        assert_eq!(true,testpkt.test_checksum());
+        assert!(testpkt.test_checksum());
     
         let buf = testpkt.serialize();
     
@@ -52,12 +167,383 @@ mod tests {
     
         let testpkt2 = testpkt2.unwrap();
 
-        assert_eq!(true,testpkt2.test_checksum());
+        assert!(testpkt2.test_checksum());
 
         assert_eq!(testpkt.checksum,testpkt2.checksum); // Verify that the two checksums actually match
 
     }
 
+    fn valid_frame() -> Vec<u8> {
+        let mut pkt = Pkt::new();
+
+        pkt.destination = Uid::new(0x1234, 0x56789ABC);
+        pkt.source = Uid::new(0xCBA9, 0x87654321);
+        pkt.cc = GET_COMMAND_RESPONSE;
+        pkt.pid = DEVICE_LABEL;
+        pkt.pd = b"Hello".to_vec();
+        pkt.pdl = pkt.pd.len() as u8;
+        assert_eq!(pkt.set_message_length(), Ok(29));
+        pkt.set_checksum();
+
+        pkt.serialize()
+    }
+
+    /// Tiny xorshift so the corpus is the same on every run without pulling in a rand crate
+    struct XorShift(u64);
+
+    impl XorShift {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+    }
+
+    fn decode_everything(data: &[u8]) {
+        if let Ok(pkt) = PktRef::new(data) {
+            let _ = (pkt.destination(), pkt.source(), pkt.subdevice(), pkt.pid(), pkt.pd(), pkt.checksum());
+        }
+        let _ = Pkt::deserialize(data.to_vec());
+        let _ = DeviceLabelPD::deserialize(data.to_vec());
+        let _ = DeviceInfoPD::deserialize(data.to_vec());
+        let _ = SensorDefinitionPD::deserialize(data.to_vec());
+        let _ = SensorValuePD::deserialize(data.to_vec());
+    }
+
+    #[test]
+    fn test_deserialize_errors() {
+        let frame = valid_frame();
+
+        assert!(Pkt::deserialize(frame.clone()).is_ok());
+
+        assert_eq!(Pkt::deserialize(vec![]).unwrap_err(), RdmError::TooShort { needed: 26, got: 0 });
+        assert_eq!(Pkt::deserialize(frame[..frame.len() - 1].to_vec()).unwrap_err(), RdmError::TooShort { needed: frame.len(), got: frame.len() - 1 });
+
+        let mut bad = frame.clone();
+        bad[0] = 0x00;
+        assert_eq!(Pkt::deserialize(bad).unwrap_err(), RdmError::BadStartCode(0x00));
+
+        let mut bad = frame.clone();
+        bad[1] = 0x02;
+        assert_eq!(Pkt::deserialize(bad).unwrap_err(), RdmError::BadSubStartCode(0x02));
+
+        // message length shorter than the header would put the checksum inside the header
+        let mut bad = frame.clone();
+        bad[2] = 10;
+        assert_eq!(Pkt::deserialize(bad).unwrap_err(), RdmError::LengthMismatch { message_length: 10, pdl: 5 });
+
+        let mut bad = frame.clone();
+        bad[23] = 0xFF;
+        assert_eq!(Pkt::deserialize(bad).unwrap_err(), RdmError::PdlOverrun { pdl: 0xFF, available: 5 });
+
+        let mut bad = frame.clone();
+        bad[23] = 4;
+        assert_eq!(Pkt::deserialize(bad).unwrap_err(), RdmError::LengthMismatch { message_length: 29, pdl: 4 });
+
+        let mut bad = frame.clone();
+        bad[24] ^= 0x01;
+        assert!(matches!(Pkt::deserialize(bad).unwrap_err(), RdmError::BadChecksum { .. }));
+
+        // trailing bytes after the checksum are not part of the frame
+        let mut long = frame.clone();
+        long.extend([0x00, 0x01]);
+        assert!(Pkt::deserialize(long).is_ok());
+    }
+
+    #[test]
+    fn test_pkt_ref_and_encode_into() {
+        let frame = valid_frame();
+        let view = PktRef::new(&frame).unwrap();
+
+        assert_eq!(view.destination(), Uid::new(0x1234, 0x56789ABC));
+        assert_eq!(view.source(), Uid::new(0xCBA9, 0x87654321));
+        assert_eq!(view.cc(), GET_COMMAND_RESPONSE);
+        assert_eq!(view.pid(), DEVICE_LABEL);
+        assert_eq!(view.pdl(), 5);
+        assert_eq!(view.pd(), b"Hello");
+        assert_eq!(view.as_bytes(), frame.as_slice());
+
+        let pkt = view.to_pkt();
+        assert!(pkt.test_checksum());
+        assert_eq!(pkt.serialize(), frame);
+
+        // encode_into works out PDL, length and checksum on its own
+        let mut unfinished = pkt.clone();
+        unfinished.pdl = 0;
+        unfinished.message_length = 0;
+        unfinished.checksum = 0;
+
+        let mut buf = [0u8; RDM_MAX_FRAME_LENGTH];
+        let len = unfinished.encode_into(&mut buf).unwrap();
+        assert_eq!(len, unfinished.encoded_len());
+        assert_eq!(&buf[..len], frame.as_slice());
+
+        // A hand-built packet is checked rather than framed with a wrapped length
+        let mut wrapped = unfinished.clone();
+        wrapped.pdl = 0xF0;
+        assert_eq!(wrapped.set_message_length(), Err(RdmError::PdTooLong(0xF0)));
+        assert_eq!(unfinished.encode_into(&mut buf[..len - 1]), Err(RdmError::BufferTooSmall { needed: len, got: len - 1 }));
+        let mut too_long = unfinished.clone();
+        too_long.pd = vec![0; RDM_MAX_PDL + 1];
+        let mut big = [0u8; 512];
+        assert_eq!(too_long.encode_into(&mut big), Err(RdmError::PdTooLong(RDM_MAX_PDL + 1)));
+    }
+
+    #[test]
+    fn test_typed_identifiers() {
+        for cc in 0..=255u8 {
+            if let Ok(typed) = CommandClass::try_from(cc) {
+                assert_eq!(u8::from(typed), cc);
+            }
+        }
+        assert_eq!(CommandClass::try_from(0x40), Err(RdmError::UnknownCommandClass(0x40)));
+        assert_eq!(CommandClass::try_from(GET_COMMAND).unwrap().to_string(), "GET_COMMAND");
+        assert!(CommandClass::SetCommandResponse.is_response());
+
+        assert_eq!(ResponseType::try_from(RESPONSE_TYPE_ACK_OVERFLOW), Ok(ResponseType::AckOverflow));
+        assert_eq!(ResponseType::try_from(0x04), Err(RdmError::UnknownResponseType(0x04)));
+
+        for pid in 0..=0xFFFFu16 {
+            assert_eq!(u16::from(ParameterId::from(pid)), pid);
+        }
+        assert_eq!(ParameterId::from(DEVICE_INFO), ParameterId::DeviceInfo);
+        assert_eq!(ParameterId::DeviceInfo.to_string(), "DEVICE_INFO");
+        assert_eq!(ParameterId::from(0x8123), ParameterId::ManufacturerSpecific(0x8123));
+        assert_eq!(ParameterId::from(0x0004), ParameterId::Unknown(0x0004));
+        assert_eq!(ParameterId::from(0x8123).to_string(), "MANUFACTURER_SPECIFIC(8123)");
+
+        let frame = valid_frame();
+        let view = PktRef::new(&frame).unwrap();
+        assert_eq!(view.command_class(), Ok(CommandClass::GetCommandResponse));
+        assert_eq!(view.response_type(), Ok(ResponseType::Ack));
+        assert_eq!(view.to_pkt().parameter_id(), ParameterId::DeviceLabel);
+    }
+
+    #[test]
+    fn test_builder() {
+        let pkt = RdmRequest::set(Uid::new(0x1234, 0x56789ABC), DEVICE_LABEL)
+            .source(Uid::new(0xCBA9, 0x87654321))
+            .tn(7)
+            .sub_device(3)
+            .pd(b"Stage Left")
+            .build()
+            .unwrap();
+
+        assert_eq!(pkt.cc, SET_COMMAND);
+        assert_eq!(pkt.port_or_response_type, 0x01);
+        assert_eq!(pkt.subdevice, 3);
+        assert_eq!(pkt.pdl, 10);
+        assert_eq!(pkt.message_length, 34);
+        assert!(pkt.test_checksum());
+
+        let decoded = Pkt::deserialize(pkt.serialize()).unwrap();
+        assert_eq!(decoded.tn, 7);
+        assert_eq!(decoded.pd, b"Stage Left");
+
+        let biggest = RdmRequest::get(Uid::new(1, 1), ParameterId::ManufacturerSpecific(0x8001))
+            .pd(&[0xAA; RDM_MAX_PDL])
+            .build()
+            .unwrap();
+        assert_eq!(biggest.message_length, 0xFF);
+        assert!(Pkt::deserialize(biggest.serialize()).is_ok());
+
+        let too_big = RdmRequest::set(Uid::new(1, 1), DEVICE_LABEL).pd(&[0; RDM_MAX_PDL + 1]).build();
+        assert_eq!(too_big.unwrap_err(), RdmError::PdTooLong(RDM_MAX_PDL + 1));
+    }
+
+    #[test]
+    fn test_response_helpers() {
+        let controller = Uid::new(0x044E, 0x00000001);
+        let device = Uid::new(0x1234, 0x56789ABC);
+
+        let request = RdmRequest::get(device, SENSOR_VALUE)
+            .source(controller)
+            .tn(42)
+            .sub_device(2)
+            .pd(&[0x01])
+            .build()
+            .unwrap();
+
+        let ack = Pkt::ack(&request, &[0x01, 0x00, 0x10, 0x00, 0x00, 0x00, 0x20, 0x00, 0x00], 3).unwrap();
+        assert_eq!(ack.source, device);
+        assert_eq!(ack.destination, controller);
+        assert_eq!(ack.tn, 42);
+        assert_eq!(ack.subdevice, 2);
+        assert_eq!(ack.pid, SENSOR_VALUE);
+        assert_eq!(ack.cc, GET_COMMAND_RESPONSE);
+        assert_eq!(ack.port_or_response_type, RESPONSE_TYPE_ACK);
+        assert_eq!(ack.pdl, 9);
+        assert_eq!(ack.message_count, 3);
+        assert!(ack.test_checksum());
+
+        let timer = Pkt::ack_timer(&request, Duration::from_millis(1250), 1).unwrap();
+        assert_eq!(timer.port_or_response_type, RESPONSE_TYPE_ACK_TIMER);
+        assert_eq!(timer.message_count, 1);
+        assert_eq!(timer.pd, vec![0x00, 13]);
+
+        let nack = Pkt::nack(&request, NackReason::DataOutOfRange, 255).unwrap();
+        assert_eq!(nack.port_or_response_type, RESPONSE_TYPE_NACK_REASON);
+        assert_eq!(nack.message_count, 255);
+        assert_eq!(nack.pd, vec![0x00, 0x06]);
+        assert_eq!(NackReason::decode(&nack.pd), Ok(NackReason::DataOutOfRange));
+
+        let overflow = Pkt::ack_overflow(&request, &[0x55; 10], 2).unwrap();
+        assert_eq!(overflow.port_or_response_type, RESPONSE_TYPE_ACK_OVERFLOW);
+        assert_eq!(overflow.pdl, 10);
+        assert_eq!(overflow.message_count, 2);
+        assert!(overflow.test_checksum());
+
+        let set = RdmRequest::set(device, DEVICE_LABEL).source(controller).build().unwrap();
+        assert_eq!(Pkt::ack(&set, &[], 0).unwrap().cc, SET_COMMAND_RESPONSE);
+
+        // A response cannot be answered
+        assert_eq!(Pkt::ack(&ack, &[], 0).unwrap_err(), RdmError::UnknownCommandClass(GET_COMMAND_RESPONSE));
+    }
+
+    #[test]
+    fn test_rdm_response() {
+        let controller = Uid::new(0x044E, 0x00000001);
+        let device = Uid::new(0x1234, 0x56789ABC);
+        let request = RdmRequest::get(device, DEVICE_LABEL).source(controller).build().unwrap();
+
+        let ack = PktBuilder::response_to(&request).unwrap().pd(b"Spot 1").message_count(2).build().unwrap();
+        let label = match RdmResponse::<DeviceLabelPD>::decode(&ack).unwrap() {
+            RdmResponse::Ack(label) => label.device_label,
+            other => panic!("expected ACK, got {:?}", other)
+        };
+        assert_eq!(label, "Spot 1");
+        assert_eq!(ack.message_count, 2);
+
+        assert_eq!(RdmResponse::decode_raw(&Pkt::ack_timer(&request, Duration::from_millis(300), 0).unwrap()), Ok(RdmResponse::AckTimer(Duration::from_millis(300))));
+        assert_eq!(RdmResponse::decode_raw(&Pkt::nack(&request, NackReason::WriteProtect, 0).unwrap()), Ok(RdmResponse::Nack(NackReason::WriteProtect)));
+        assert_eq!(RdmResponse::decode_raw(&Pkt::ack_overflow(&request, &[0x41; 4], 0).unwrap()), Ok(RdmResponse::AckOverflow(vec![0x41; 4])));
+        assert_eq!(RdmResponse::decode_raw(&ack).map(|response| response.map(|pd| pd.len())), Ok(RdmResponse::Ack(6)));
+
+        // A request is not a response, even though its port ID reads as a response type
+        assert_eq!(RdmResponse::decode_raw(&request), Err(RdmError::UnexpectedResponse));
+
+        let short_timer = PktBuilder::response_to(&request).unwrap().response_type(ResponseType::AckTimer).pd(&[0x01]).build().unwrap();
+        assert_eq!(RdmResponse::decode_raw(&short_timer), Err(RdmError::TooShort { needed: 2, got: 1 }));
+    }
+
+    #[test]
+    fn test_parameter_data() {
+        let controller = Uid::new(0x044E, 0x00000001);
+        let device = Uid::new(0x1234, 0x56789ABC);
+
+        assert_eq!(<DeviceInfoPD as ParameterData>::PID, DEVICE_INFO);
+        assert_eq!(u8::from(<DeviceLabelPD as ParameterData>::COMMAND_CLASS), 0x03);
+        assert!(SensorDefinitionPD::COMMAND_CLASS.supports(CommandClass::GetCommand));
+        assert!(!SensorDefinitionPD::COMMAND_CLASS.supports(CommandClass::SetCommand));
+        assert!(!ParameterCommandClass::GetSet.supports(CommandClass::DiscoveryCommand));
+
+        // A responder dispatching on type
+        let get = RdmRequest::get(device, DEVICE_LABEL).source(controller).build().unwrap();
+        assert!(matches!(ParameterRequest::<DeviceLabelPD>::decode(&get), Ok(ParameterRequest::Get(()))));
+
+        let set = RdmRequest::set(device, DEVICE_LABEL).source(controller).pd(b"Wash 4").build().unwrap();
+        match ParameterRequest::<DeviceLabelPD>::decode(&set) {
+            Ok(ParameterRequest::Set(label)) => assert_eq!(label.device_label, "Wash 4"),
+            other => panic!("expected SET, got {:?}", other)
+        }
+
+        let sensor = RdmRequest::get(device, SENSOR_VALUE).source(controller).pd(&[3]).build().unwrap();
+        assert!(matches!(ParameterRequest::<SensorValuePD>::decode(&sensor), Ok(ParameterRequest::Get(3))));
+
+        let set_info = RdmRequest::set(device, DEVICE_INFO).source(controller).build().unwrap();
+        assert_eq!(ParameterRequest::<DeviceInfoPD>::decode(&set_info).unwrap_err(), NackReason::UnsupportedCommandClass);
+        assert_eq!(ParameterRequest::<DeviceInfoPD>::decode(&get).unwrap_err(), NackReason::UnknownPid);
+
+        let no_sensor = RdmRequest::get(device, SENSOR_VALUE).source(controller).build().unwrap();
+        assert_eq!(ParameterRequest::<SensorValuePD>::decode(&no_sensor).unwrap_err(), NackReason::FormatError);
+
+        assert_eq!(<u16 as PdCodec>::deserialize(vec![0x01, 0x02]), Ok(0x0102));
+        assert_eq!(<u16 as PdCodec>::deserialize(vec![0x01]), Err(RdmError::TooShort { needed: 2, got: 1 }));
+    }
+
+    #[test]
+    fn test_nack_reasons() {
+        for code in 0..=0x0013u16 {
+            let reason = NackReason::from(code);
+            assert!(!matches!(reason, NackReason::Unknown(_)));
+            assert_eq!(u16::from(reason), code);
+            assert_eq!(NackReason::decode(&reason.encode()), Ok(reason));
+        }
+
+        assert_eq!(NackReason::from(0x8001), NackReason::Unknown(0x8001));
+        assert_eq!(u16::from(NackReason::Unknown(0x8001)), 0x8001);
+
+        assert_eq!(NackReason::decode(&[0x00, 0x04]), Ok(NackReason::WriteProtect));
+        assert_eq!(NackReason::decode(&[0x00]), Err(RdmError::TooShort { needed: 2, got: 1 }));
+
+        assert_eq!(NackReason::ProxyBufferFull.name(), "NR_PROXY_BUFFER_FULL");
+        assert_eq!(NackReason::WriteProtect.to_string(), "SET command failed because the value is write protected");
+        assert_eq!(NackReason::Unknown(0x8001).to_string(), "unknown NACK reason 8001");
+    }
+
+    #[test]
+    fn test_pd_deserialize_errors() {
+        assert_eq!(DeviceInfoPD::deserialize(vec![0; 18]).unwrap_err(), RdmError::TooShort { needed: 19, got: 18 });
+        assert_eq!(SensorDefinitionPD::deserialize(vec![0; 12]).unwrap_err(), RdmError::TooShort { needed: 13, got: 12 });
+        assert_eq!(SensorValuePD::deserialize(vec![]).unwrap_err(), RdmError::TooShort { needed: 9, got: 0 });
+
+        assert_eq!(DeviceLabelPD::deserialize(b"Label\0junk".to_vec()).unwrap().device_label, "Label");
+    }
+
+    #[test]
+    fn test_deserialize_corpus_never_panics() {
+        let frame = valid_frame();
+
+        // Every truncation of a valid frame
+        for len in 0..=frame.len() {
+            decode_everything(&frame[..len]);
+        }
+
+        // Every value in every header slot, which covers hostile lengths and PDLs
+        for index in 0..RDM_HEADER_LENGTH {
+            for value in 0..=255u8 {
+                let mut mutated = frame.clone();
+                mutated[index] = value;
+                decode_everything(&mutated);
+            }
+        }
+
+        // Hostile lengths with a buffer that is exactly as long as the header says
+        for message_length in 0..=255u8 {
+            for pdl in [0u8, 1, 231, 232, 255] {
+                let mut hostile = vec![0u8; message_length as usize + 2];
+                hostile.resize(hostile.len().max(RDM_MIN_FRAME_LENGTH), 0);
+                hostile[0] = SC_RDM;
+                hostile[1] = SC_SUB_MESSAGE;
+                hostile[2] = message_length;
+                hostile[23] = pdl;
+                decode_everything(&hostile);
+            }
+        }
+
+        // Random buffers and random corruptions of a valid frame
+        let mut rng = XorShift(0x0123_4567_89AB_CDEF);
+
+        for _ in 0..20_000 {
+            let len = (rng.next() % 300) as usize;
+            let mut random : Vec<u8> = (0..len).map(|_| rng.next() as u8).collect();
+            if len > 2 && rng.next() & 1 == 0 {
+                random[0] = SC_RDM;
+                random[1] = SC_SUB_MESSAGE;
+            }
+            decode_everything(&random);
+
+            let mut mutated = frame.clone();
+            for _ in 0..(rng.next() % 4 + 1) {
+                let index = (rng.next() as usize) % mutated.len();
+                mutated[index] = rng.next() as u8;
+            }
+            mutated.truncate((rng.next() as usize) % (mutated.len() + 1));
+            decode_everything(&mutated);
+        }
+    }
+
 }
 
 // A big ol table of pub constants
@@ -80,6 +566,28 @@ pub const RESPONSE_TYPE_ACK_TIMER : u8      = 0x01;
 pub const RESPONSE_TYPE_NACK_REASON : u8    = 0x02;
 pub const RESPONSE_TYPE_ACK_OVERFLOW : u8   = 0x03;
 
+// Table A-17: Response NACK Reason Code Defines
+pub const NR_UNKNOWN_PID : u16                  = 0x0000;
+pub const NR_FORMAT_ERROR : u16                 = 0x0001;
+pub const NR_HARDWARE_FAULT : u16               = 0x0002;
+pub const NR_PROXY_REJECT : u16                 = 0x0003;
+pub const NR_WRITE_PROTECT : u16                = 0x0004;
+pub const NR_UNSUPPORTED_COMMAND_CLASS : u16    = 0x0005;
+pub const NR_DATA_OUT_OF_RANGE : u16            = 0x0006;
+pub const NR_BUFFER_FULL : u16                  = 0x0007;
+pub const NR_PACKET_SIZE_UNSUPPORTED : u16      = 0x0008;
+pub const NR_SUB_DEVICE_OUT_OF_RANGE : u16      = 0x0009;
+pub const NR_PROXY_BUFFER_FULL : u16            = 0x000A; /* Added in E1.20-2010 */
+pub const NR_ACTION_NOT_SUPPORTED : u16         = 0x000B; /* Defined in ANSI E1.37-2 */
+pub const NR_ENDPOINT_NUMBER_INVALID : u16      = 0x000C; /* Defined in ANSI E1.37-7 */
+pub const NR_INVALID_ENDPOINT_MODE : u16        = 0x000D; /* Defined in ANSI E1.37-7 */
+pub const NR_UNKNOWN_UID : u16                  = 0x000E; /* Defined in ANSI E1.37-7 */
+pub const NR_UNKNOWN_SCOPE : u16                = 0x000F; /* Defined in ANSI E1.33 */
+pub const NR_INVALID_STATIC_CONFIG_TYPE : u16   = 0x0010; /* Defined in ANSI E1.33 */
+pub const NR_INVALID_IPV4_ADDRESS : u16         = 0x0011; /* Defined in ANSI E1.33 */
+pub const NR_INVALID_IPV6_ADDRESS : u16         = 0x0012; /* Defined in ANSI E1.33 */
+pub const NR_INVALID_PORT : u16                 = 0x0013; /* Defined in ANSI E1.33 */
+
 // Table A-3: Table A-3: RDM Categories/Parameter ID Defines
 // These are almost exclusively lifted from here: https://github.com/ETCLabs/ETCDmxTool/blob/33f9aafcb7f0f78f59fc8ad3441878762202330a/src/rdm/estardm.h
 // Copyright notice:
@@ -205,17 +713,440 @@ pub const PRESET_MERGEMODE                           : u16 = 0x1043; /* See E1.3
 pub const POWER_ON_SELF_TEST                         : u16 = 0x1044; /* Defined in ANSI E1.37-1                                     */
 
 
+/// Typed Table A-1 command class
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CommandClass {
+    DiscoveryCommand,
+    DiscoveryCommandResponse,
+    GetCommand,
+    GetCommandResponse,
+    SetCommand,
+    SetCommandResponse
+}
+
+impl CommandClass {
+    /// True for the three *_RESPONSE classes
+    pub fn is_response(self) -> bool {
+        matches!(self, CommandClass::DiscoveryCommandResponse | CommandClass::GetCommandResponse | CommandClass::SetCommandResponse)
+    }
+
+    /// The class a responder answers this request with, None if this is already a response
+    pub fn response(self) -> Option<CommandClass> {
+        match self {
+            CommandClass::DiscoveryCommand => Some(CommandClass::DiscoveryCommandResponse),
+            CommandClass::GetCommand => Some(CommandClass::GetCommandResponse),
+            CommandClass::SetCommand => Some(CommandClass::SetCommandResponse),
+            _ => None
+        }
+    }
+}
+
+impl TryFrom<u8> for CommandClass {
+    type Error = RdmError;
+
+    fn try_from(cc: u8) -> Result<Self, Self::Error> {
+        match cc {
+            DISCOVERY_COMMAND => Ok(CommandClass::DiscoveryCommand),
+            DISCOVERY_COMMAND_RESPONSE => Ok(CommandClass::DiscoveryCommandResponse),
+            GET_COMMAND => Ok(CommandClass::GetCommand),
+            GET_COMMAND_RESPONSE => Ok(CommandClass::GetCommandResponse),
+            SET_COMMAND => Ok(CommandClass::SetCommand),
+            SET_COMMAND_RESPONSE => Ok(CommandClass::SetCommandResponse),
+            _ => Err(RdmError::UnknownCommandClass(cc))
+        }
+    }
+}
+
+impl From<CommandClass> for u8 {
+    fn from(cc: CommandClass) -> u8 {
+        match cc {
+            CommandClass::DiscoveryCommand => DISCOVERY_COMMAND,
+            CommandClass::DiscoveryCommandResponse => DISCOVERY_COMMAND_RESPONSE,
+            CommandClass::GetCommand => GET_COMMAND,
+            CommandClass::GetCommandResponse => GET_COMMAND_RESPONSE,
+            CommandClass::SetCommand => SET_COMMAND,
+            CommandClass::SetCommandResponse => SET_COMMAND_RESPONSE
+        }
+    }
+}
+
+impl fmt::Display for CommandClass {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            CommandClass::DiscoveryCommand => "DISCOVERY_COMMAND",
+            CommandClass::DiscoveryCommandResponse => "DISCOVERY_COMMAND_RESPONSE",
+            CommandClass::GetCommand => "GET_COMMAND",
+            CommandClass::GetCommandResponse => "GET_COMMAND_RESPONSE",
+            CommandClass::SetCommand => "SET_COMMAND",
+            CommandClass::SetCommandResponse => "SET_COMMAND_RESPONSE"
+        })
+    }
+}
+
+/// Typed Table A-2 response type
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ResponseType {
+    Ack,
+    AckTimer,
+    NackReason,
+    AckOverflow
+}
+
+impl TryFrom<u8> for ResponseType {
+    type Error = RdmError;
+
+    fn try_from(rt: u8) -> Result<Self, Self::Error> {
+        match rt {
+            RESPONSE_TYPE_ACK => Ok(ResponseType::Ack),
+            RESPONSE_TYPE_ACK_TIMER => Ok(ResponseType::AckTimer),
+            RESPONSE_TYPE_NACK_REASON => Ok(ResponseType::NackReason),
+            RESPONSE_TYPE_ACK_OVERFLOW => Ok(ResponseType::AckOverflow),
+            _ => Err(RdmError::UnknownResponseType(rt))
+        }
+    }
+}
+
+impl From<ResponseType> for u8 {
+    fn from(rt: ResponseType) -> u8 {
+        match rt {
+            ResponseType::Ack => RESPONSE_TYPE_ACK,
+            ResponseType::AckTimer => RESPONSE_TYPE_ACK_TIMER,
+            ResponseType::NackReason => RESPONSE_TYPE_NACK_REASON,
+            ResponseType::AckOverflow => RESPONSE_TYPE_ACK_OVERFLOW
+        }
+    }
+}
+
+impl fmt::Display for ResponseType {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            ResponseType::Ack => "RESPONSE_TYPE_ACK",
+            ResponseType::AckTimer => "RESPONSE_TYPE_ACK_TIMER",
+            ResponseType::NackReason => "RESPONSE_TYPE_NACK_REASON",
+            ResponseType::AckOverflow => "RESPONSE_TYPE_ACK_OVERFLOW"
+        })
+    }
+}
+
+/// Typed NACK reason from E1.20 Table A-17 and the E1.37/E1.33 additions
+/// Carried as the two byte PD of a RESPONSE_TYPE_NACK_REASON reply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NackReason {
+    UnknownPid,
+    FormatError,
+    HardwareFault,
+    ProxyReject,
+    WriteProtect,
+    UnsupportedCommandClass,
+    DataOutOfRange,
+    BufferFull,
+    PacketSizeUnsupported,
+    SubDeviceOutOfRange,
+    ProxyBufferFull,
+    ActionNotSupported,
+    EndpointNumberInvalid,
+    InvalidEndpointMode,
+    UnknownUid,
+    UnknownScope,
+    InvalidStaticConfigType,
+    InvalidIpv4Address,
+    InvalidIpv6Address,
+    InvalidPort,
+    Unknown(u16)
+}
+
+impl NackReason {
+    /// Reads the reason code out of a NACK response's parameter data
+    pub fn decode(pd: &[u8]) -> Result<NackReason, RdmError> {
+        if pd.len() < 2 {
+            return Err(RdmError::TooShort { needed: 2, got: pd.len() });
+        }
+
+        Ok(NackReason::from(u16::from_be_bytes([pd[0], pd[1]])))
+    }
+
+    pub fn encode(self) -> [u8; 2] {
+        u16::from(self).to_be_bytes()
+    }
+
+    /// The define name, e.g. NR_WRITE_PROTECT
+    pub fn name(self) -> &'static str {
+        match self {
+            NackReason::UnknownPid => "NR_UNKNOWN_PID",
+            NackReason::FormatError => "NR_FORMAT_ERROR",
+            NackReason::HardwareFault => "NR_HARDWARE_FAULT",
+            NackReason::ProxyReject => "NR_PROXY_REJECT",
+            NackReason::WriteProtect => "NR_WRITE_PROTECT",
+            NackReason::UnsupportedCommandClass => "NR_UNSUPPORTED_COMMAND_CLASS",
+            NackReason::DataOutOfRange => "NR_DATA_OUT_OF_RANGE",
+            NackReason::BufferFull => "NR_BUFFER_FULL",
+            NackReason::PacketSizeUnsupported => "NR_PACKET_SIZE_UNSUPPORTED",
+            NackReason::SubDeviceOutOfRange => "NR_SUB_DEVICE_OUT_OF_RANGE",
+            NackReason::ProxyBufferFull => "NR_PROXY_BUFFER_FULL",
+            NackReason::ActionNotSupported => "NR_ACTION_NOT_SUPPORTED",
+            NackReason::EndpointNumberInvalid => "NR_ENDPOINT_NUMBER_INVALID",
+            NackReason::InvalidEndpointMode => "NR_INVALID_ENDPOINT_MODE",
+            NackReason::UnknownUid => "NR_UNKNOWN_UID",
+            NackReason::UnknownScope => "NR_UNKNOWN_SCOPE",
+            NackReason::InvalidStaticConfigType => "NR_INVALID_STATIC_CONFIG_TYPE",
+            NackReason::InvalidIpv4Address => "NR_INVALID_IPV4_ADDRESS",
+            NackReason::InvalidIpv6Address => "NR_INVALID_IPV6_ADDRESS",
+            NackReason::InvalidPort => "NR_INVALID_PORT",
+            NackReason::Unknown(_) => "NR_UNKNOWN"
+        }
+    }
+
+    /// Wording from the standard, suitable for showing to a technician
+    pub fn description(self) -> &'static str {
+        match self {
+            NackReason::UnknownPid => "The responder cannot comply with the request because the PID is not implemented",
+            NackReason::FormatError => "The responder cannot interpret the request as it does not match the PID format",
+            NackReason::HardwareFault => "The responder cannot comply due to an internal hardware fault",
+            NackReason::ProxyReject => "Proxy is not the RDM line master and cannot comply with the message",
+            NackReason::WriteProtect => "SET command failed because the value is write protected",
+            NackReason::UnsupportedCommandClass => "The command class is not supported for this PID",
+            NackReason::DataOutOfRange => "The value or values are out of range for this PID",
+            NackReason::BufferFull => "The responder's buffer or queue is full",
+            NackReason::PacketSizeUnsupported => "The incoming message exceeds the responder's buffer size",
+            NackReason::SubDeviceOutOfRange => "The sub-device is out of range or unknown",
+            NackReason::ProxyBufferFull => "The proxy's buffer is full and it cannot store any more queued message or status message responses",
+            NackReason::ActionNotSupported => "The parameter data is valid but the SET operation cannot be performed with the current configuration",
+            NackReason::EndpointNumberInvalid => "The endpoint number is invalid",
+            NackReason::InvalidEndpointMode => "The endpoint mode is invalid",
+            NackReason::UnknownUid => "The UID is not known to the responder",
+            NackReason::UnknownScope => "The component is not participating in the given scope",
+            NackReason::InvalidStaticConfigType => "The static configuration type is invalid",
+            NackReason::InvalidIpv4Address => "The IPv4 address is invalid",
+            NackReason::InvalidIpv6Address => "The IPv6 address is invalid",
+            NackReason::InvalidPort => "The transport layer port is invalid",
+            NackReason::Unknown(_) => "The responder gave a reason code this library does not know"
+        }
+    }
+}
+
+impl From<u16> for NackReason {
+    fn from(reason: u16) -> Self {
+        match reason {
+            NR_UNKNOWN_PID => NackReason::UnknownPid,
+            NR_FORMAT_ERROR => NackReason::FormatError,
+            NR_HARDWARE_FAULT => NackReason::HardwareFault,
+            NR_PROXY_REJECT => NackReason::ProxyReject,
+            NR_WRITE_PROTECT => NackReason::WriteProtect,
+            NR_UNSUPPORTED_COMMAND_CLASS => NackReason::UnsupportedCommandClass,
+            NR_DATA_OUT_OF_RANGE => NackReason::DataOutOfRange,
+            NR_BUFFER_FULL => NackReason::BufferFull,
+            NR_PACKET_SIZE_UNSUPPORTED => NackReason::PacketSizeUnsupported,
+            NR_SUB_DEVICE_OUT_OF_RANGE => NackReason::SubDeviceOutOfRange,
+            NR_PROXY_BUFFER_FULL => NackReason::ProxyBufferFull,
+            NR_ACTION_NOT_SUPPORTED => NackReason::ActionNotSupported,
+            NR_ENDPOINT_NUMBER_INVALID => NackReason::EndpointNumberInvalid,
+            NR_INVALID_ENDPOINT_MODE => NackReason::InvalidEndpointMode,
+            NR_UNKNOWN_UID => NackReason::UnknownUid,
+            NR_UNKNOWN_SCOPE => NackReason::UnknownScope,
+            NR_INVALID_STATIC_CONFIG_TYPE => NackReason::InvalidStaticConfigType,
+            NR_INVALID_IPV4_ADDRESS => NackReason::InvalidIpv4Address,
+            NR_INVALID_IPV6_ADDRESS => NackReason::InvalidIpv6Address,
+            NR_INVALID_PORT => NackReason::InvalidPort,
+            _ => NackReason::Unknown(reason)
+        }
+    }
+}
+
+impl From<NackReason> for u16 {
+    fn from(reason: NackReason) -> u16 {
+        match reason {
+            NackReason::UnknownPid => NR_UNKNOWN_PID,
+            NackReason::FormatError => NR_FORMAT_ERROR,
+            NackReason::HardwareFault => NR_HARDWARE_FAULT,
+            NackReason::ProxyReject => NR_PROXY_REJECT,
+            NackReason::WriteProtect => NR_WRITE_PROTECT,
+            NackReason::UnsupportedCommandClass => NR_UNSUPPORTED_COMMAND_CLASS,
+            NackReason::DataOutOfRange => NR_DATA_OUT_OF_RANGE,
+            NackReason::BufferFull => NR_BUFFER_FULL,
+            NackReason::PacketSizeUnsupported => NR_PACKET_SIZE_UNSUPPORTED,
+            NackReason::SubDeviceOutOfRange => NR_SUB_DEVICE_OUT_OF_RANGE,
+            NackReason::ProxyBufferFull => NR_PROXY_BUFFER_FULL,
+            NackReason::ActionNotSupported => NR_ACTION_NOT_SUPPORTED,
+            NackReason::EndpointNumberInvalid => NR_ENDPOINT_NUMBER_INVALID,
+            NackReason::InvalidEndpointMode => NR_INVALID_ENDPOINT_MODE,
+            NackReason::UnknownUid => NR_UNKNOWN_UID,
+            NackReason::UnknownScope => NR_UNKNOWN_SCOPE,
+            NackReason::InvalidStaticConfigType => NR_INVALID_STATIC_CONFIG_TYPE,
+            NackReason::InvalidIpv4Address => NR_INVALID_IPV4_ADDRESS,
+            NackReason::InvalidIpv6Address => NR_INVALID_IPV6_ADDRESS,
+            NackReason::InvalidPort => NR_INVALID_PORT,
+            NackReason::Unknown(reason) => reason
+        }
+    }
+}
+
+impl fmt::Display for NackReason {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            NackReason::Unknown(reason) => write!(f, "unknown NACK reason {:04X}", reason),
+            _ => f.write_str(self.description())
+        }
+    }
+}
+
+/// Manufacturer-specific PIDs live in 0x8000-0xFFDF (E1.20 Table A-3)
+pub const PID_MANUFACTURER_SPECIFIC_MIN : u16 = 0x8000;
+pub const PID_MANUFACTURER_SPECIFIC_MAX : u16 = 0xFFDF;
+
+macro_rules! parameter_ids {
+    ($($variant:ident => $pid:ident),* $(,)?) => {
+        /// Typed Table A-3 parameter ID
+        /// Every u16 converts: values outside the table land in ManufacturerSpecific or Unknown.
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum ParameterId {
+            $($variant,)*
+            ManufacturerSpecific(u16),
+            Unknown(u16)
+        }
+
+        impl From<u16> for ParameterId {
+            fn from(pid: u16) -> Self {
+                match pid {
+                    $($pid => ParameterId::$variant,)*
+                    PID_MANUFACTURER_SPECIFIC_MIN..=PID_MANUFACTURER_SPECIFIC_MAX => ParameterId::ManufacturerSpecific(pid),
+                    _ => ParameterId::Unknown(pid)
+                }
+            }
+        }
+
+        impl From<ParameterId> for u16 {
+            fn from(pid: ParameterId) -> u16 {
+                match pid {
+                    $(ParameterId::$variant => $pid,)*
+                    ParameterId::ManufacturerSpecific(pid) | ParameterId::Unknown(pid) => pid
+                }
+            }
+        }
+
+        impl fmt::Display for ParameterId {
+            fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                match self {
+                    $(ParameterId::$variant => f.write_str(stringify!($pid)),)*
+                    ParameterId::ManufacturerSpecific(pid) => write!(f, "MANUFACTURER_SPECIFIC({:04X})", pid),
+                    ParameterId::Unknown(pid) => write!(f, "UNKNOWN({:04X})", pid)
+                }
+            }
+        }
+    };
+}
+
+parameter_ids! {
+    DiscUniqueBranch               => DISC_UNIQUE_BRANCH,
+    DiscMute                       => DISC_MUTE,
+    DiscUnMute                     => DISC_UN_MUTE,
+    ProxiedDevices                 => PROXIED_DEVICES,
+    ProxiedDeviceCount             => PROXIED_DEVICE_COUNT,
+    CommsStatus                    => COMMS_STATUS,
+    QueuedMessage                  => QUEUED_MESSAGE,
+    StatusMessages                 => STATUS_MESSAGES,
+    StatusIdDescription            => STATUS_ID_DESCRIPTION,
+    ClearStatusId                  => CLEAR_STATUS_ID,
+    SubDeviceStatusReportThreshold => SUB_DEVICE_STATUS_REPORT_THRESHOLD,
+    SupportedParameters            => SUPPORTED_PARAMETERS,
+    ParameterDescription           => PARAMETER_DESCRIPTION,
+    DeviceInfo                     => DEVICE_INFO,
+    ProductDetailIdList            => PRODUCT_DETAIL_ID_LIST,
+    DeviceModelDescription         => DEVICE_MODEL_DESCRIPTION,
+    ManufacturerLabel              => MANUFACTURER_LABEL,
+    DeviceLabel                    => DEVICE_LABEL,
+    FactoryDefaults                => FACTORY_DEFAULTS,
+    LanguageCapabilities           => LANGUAGE_CAPABILITIES,
+    Language                       => LANGUAGE,
+    SoftwareVersionLabel           => SOFTWARE_VERSION_LABEL,
+    BootSoftwareVersionId          => BOOT_SOFTWARE_VERSION_ID,
+    BootSoftwareVersionLabel       => BOOT_SOFTWARE_VERSION_LABEL,
+    DmxPersonality                 => DMX_PERSONALITY,
+    DmxPersonalityDescription      => DMX_PERSONALITY_DESCRIPTION,
+    DmxStartAddress                => DMX_START_ADDRESS,
+    SlotInfo                       => SLOT_INFO,
+    SlotDescription                => SLOT_DESCRIPTION,
+    DefaultSlotValue               => DEFAULT_SLOT_VALUE,
+    DmxBlockAddress                => DMX_BLOCK_ADDRESS,
+    DmxFailMode                    => DMX_FAIL_MODE,
+    DmxStartupMode                 => DMX_STARTUP_MODE,
+    SensorDefinition               => SENSOR_DEFINITION,
+    SensorValue                    => SENSOR_VALUE,
+    RecordSensors                  => RECORD_SENSORS,
+    DimmerInfo                     => DIMMER_INFO,
+    MinimumLevel                   => MINIMUM_LEVEL,
+    MaximumLevel                   => MAXIMUM_LEVEL,
+    Curve                          => CURVE,
+    CurveDescription               => CURVE_DESCRIPTION,
+    OutputResponseTime             => OUTPUT_RESPONSE_TIME,
+    OutputResponseTimeDescription  => OUTPUT_RESPONSE_TIME_DESCRIPTION,
+    ModulationFrequency            => MODULATION_FREQUENCY,
+    ModulationFrequencyDescription => MODULATION_FREQUENCY_DESCRIPTION,
+    DeviceHours                    => DEVICE_HOURS,
+    LampHours                      => LAMP_HOURS,
+    LampStrikes                    => LAMP_STRIKES,
+    LampState                      => LAMP_STATE,
+    LampOnMode                     => LAMP_ON_MODE,
+    DevicePowerCycles              => DEVICE_POWER_CYCLES,
+    BurnIn                         => BURN_IN,
+    DisplayInvert                  => DISPLAY_INVERT,
+    DisplayLevel                   => DISPLAY_LEVEL,
+    PanInvert                      => PAN_INVERT,
+    TiltInvert                     => TILT_INVERT,
+    PanTiltSwap                    => PAN_TILT_SWAP,
+    RealTimeClock                  => REAL_TIME_CLOCK,
+    LockPin                        => LOCK_PIN,
+    LockState                      => LOCK_STATE,
+    LockStateDescription           => LOCK_STATE_DESCRIPTION,
+    IdentifyDevice                 => IDENTIFY_DEVICE,
+    ResetDevice                    => RESET_DEVICE,
+    PowerState                     => POWER_STATE,
+    PerformSelftest                => PERFORM_SELFTEST,
+    SelfTestDescription            => SELF_TEST_DESCRIPTION,
+    CapturePreset                  => CAPTURE_PRESET,
+    PresetPlayback                 => PRESET_PLAYBACK,
+    IdentifyMode                   => IDENTIFY_MODE,
+    PresetInfo                     => PRESET_INFO,
+    PresetStatus                   => PRESET_STATUS,
+    PresetMergemode                => PRESET_MERGEMODE,
+    PowerOnSelfTest                => POWER_ON_SELF_TEST
+}
+
+
 /// UID Struct
 /// This contains the manufacturer and device ids
 /// Implementations will include the ability to format as MMMM:DDDDDDDD as well as strings of bytes
 /// 
-#[derive(Copy, Clone, Serialize, Deserialize)]
+/// Ordered by manufacturer and then device, which is the order discovery finds them in.
+#[derive(Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Uid {
     pub mfg : u16,
     pub dev : u32
 }
 
 impl Uid {
+    /// Addresses every device on the line
+    pub const BROADCAST : Uid = Uid { mfg: 0xFFFF, dev: 0xFFFF_FFFF };
+
+    /// Addresses every device made by mfg
+    pub const fn vendorcast(mfg: u16) -> Uid {
+        Uid { mfg, dev: 0xFFFF_FFFF }
+    }
+
+    pub fn is_broadcast(&self) -> bool {
+        *self == Uid::BROADCAST
+    }
+
+    pub fn is_vendorcast(&self) -> bool {
+        self.dev == 0xFFFF_FFFF && self.mfg != 0xFFFF
+    }
+
+    /// True if a request sent to this UID is for target: target itself, the broadcast UID, or
+    /// target's manufacturer's vendorcast UID
+    pub fn addresses(&self, target: &Uid) -> bool {
+        self == target
+            || self.is_broadcast()
+            || (self.is_vendorcast() && self.mfg == target.mfg)
+    }
 
     pub fn uid_serialize(self) -> [u8; 6] {
         let mfg : [u8; 2] = self.mfg.to_be_bytes();
@@ -226,39 +1157,39 @@ impl Uid {
         ret[0..2].clone_from_slice(&mfg);
         ret[2..].clone_from_slice(&dev);
 
-        return ret;
+        ret
     }
 
-    pub fn from_bytes(data: &[u8]) -> Uid {
-
-        let mut mfg_array = [0u8; 2];
-        let mut dev_array = [0u8; 4];
-
-        mfg_array.clone_from_slice(&data[0..2]);
-        dev_array.clone_from_slice(&data[2..6]);
-
+    /// The inverse of uid_serialize
+    pub fn from_array(data: [u8; 6]) -> Uid {
+        Uid { mfg: u16::from_be_bytes([data[0], data[1]]),
+            dev: u32::from_be_bytes([data[2], data[3], data[4], data[5]])}
+    }
 
-        Uid { mfg: u16::from_be_bytes(mfg_array),
-            dev: u32::from_be_bytes(dev_array)}
+    /// Reads a UID from the first 6 bytes of data
+    pub fn from_bytes(data: &[u8]) -> Result<Uid, RdmError> {
+        match data.get(0..6) {
+            Some(bytes) => Ok(Uid::from_array(bytes.try_into().unwrap())),
+            None => Err(RdmError::TooShort { needed: 6, got: data.len() })
+        }
     }
 
     /// Given a UID in the standard format like 044E:123456 it'll generate a UID
+    /// Anything that does not parse comes back as 0000:00000000.
+    #[deprecated(note = "use str::parse, which reports bad input")]
     pub fn from_string(data: String) -> Uid {
-        Uid { 
-            mfg: u16::from_str_radix(&data[0..4],16).unwrap_or(0x00),  
-            dev: u32::from_str_radix(&data[5..], 16).unwrap_or(0x00)            
-        }
+        data.parse().unwrap_or(Uid::new(0, 0))
     }
 
     pub fn new(mfg : u16, dev : u32) -> Uid {
         Uid { mfg , dev }
     }
 
-    pub fn set_mfg(mut self, mfg_id: u16) -> () {
+    pub fn set_mfg(&mut self, mfg_id: u16) {
         self.mfg = mfg_id;
     }
 
-    pub fn set_dev(mut self, dev_id: u32) -> () {
+    pub fn set_dev(&mut self, dev_id: u32) {
         self.dev = dev_id;
     }
 
@@ -274,11 +1205,9 @@ impl Uid {
         ((self.mfg as u64) << 32) + (self.dev as u64)
     }
 
-    pub fn set_from_64(mut self, val: u64) -> () {
-        println!("Val: {} mfg: {} dev: {}",val,(val >> 32) as u16,val as u32);
+    pub fn set_from_64(&mut self, val: u64) {
         self.mfg = (val >> 32) as u16;
         self.dev = val as u32;
-        println!("mfg: {} dev: {}",self.mfg,self.dev);
     }
 
     pub fn new_from_64(val: u64) -> Uid {
@@ -288,6 +1217,7 @@ impl Uid {
         }
     }
 
+    #[deprecated(note = "use UidRange::split_half, which never probes the midpoint from both sides")]
     pub fn get_midpoint(self, top: &Uid) -> Uid {
         if self == *top {
             return self;
@@ -343,6 +1273,167 @@ impl fmt::Debug for Uid {
     }
 }
 
+/// Why a string is not a UID
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ParseUidError {
+    /// Neither MMMM:DDDDDDDD nor 12 hex digits; holds the number of digits found
+    BadLength(usize),
+    /// A character that is not a hex digit
+    BadDigit(char)
+}
+
+impl fmt::Display for ParseUidError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseUidError::BadLength(len) => write!(f, "expected MMMM:DDDDDDDD or 12 hex digits, got {} digits", len),
+            ParseUidError::BadDigit(c) => write!(f, "{:?} is not a hex digit", c),
+        }
+    }
+}
+
+impl std::error::Error for ParseUidError {}
+
+/// Accepts 044E:01234567, 044e01234567 and 0x044E01234567.  With the colon, leading zeros
+/// may be left off either half.
+impl str::FromStr for Uid {
+    type Err = ParseUidError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let s = s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")).unwrap_or(s);
+
+        if let Some(c) = s.chars().find(|c| !c.is_ascii_hexdigit() && *c != ':') {
+            return Err(ParseUidError::BadDigit(c));
+        }
+
+        let (mfg, dev) = match s.split_once(':') {
+            Some((mfg, dev)) => {
+                if dev.contains(':') {
+                    return Err(ParseUidError::BadDigit(':'));
+                }
+                if mfg.is_empty() || mfg.len() > 4 || dev.is_empty() || dev.len() > 8 {
+                    return Err(ParseUidError::BadLength(mfg.len() + dev.len()));
+                }
+                (mfg, dev)
+            },
+            None => {
+                if s.len() != 12 {
+                    return Err(ParseUidError::BadLength(s.len()));
+                }
+                s.split_at(4)
+            }
+        };
+
+        // Only hex digits are left, so these cannot fail
+        Ok(Uid {
+            mfg: u16::from_str_radix(mfg, 16).unwrap(),
+            dev: u32::from_str_radix(dev, 16).unwrap()
+        })
+    }
+}
+
+/// Number of bytes from the start code up to and including the PDL slot
+pub const RDM_HEADER_LENGTH : usize = 24;
+/// Smallest possible frame: a header with no parameter data followed by the checksum
+pub const RDM_MIN_FRAME_LENGTH : usize = RDM_HEADER_LENGTH + 2;
+/// Largest PDL that still fits in the 8 bit message length slot
+pub const RDM_MAX_PDL : usize = 0xFF - RDM_HEADER_LENGTH;
+/// Largest possible frame, sized for stack buffers passed to Pkt::encode_into
+pub const RDM_MAX_FRAME_LENGTH : usize = 0xFF + 2;
+
+/// Errors returned when decoding frames and parameter data
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RdmError {
+    /// The buffer ended before the data it claims to carry
+    TooShort { needed: usize, got: usize },
+    /// First byte was not SC_RDM
+    BadStartCode(u8),
+    /// Second byte was not SC_SUB_MESSAGE
+    BadSubStartCode(u8),
+    /// Message length slot disagrees with the PDL (message length must be PDL + 24)
+    LengthMismatch { message_length: u8, pdl: u8 },
+    /// PDL claims more parameter data than the message length leaves room for
+    PdlOverrun { pdl: u8, available: usize },
+    /// Checksum slots do not match the sum of the message
+    BadChecksum { expected: u16, received: u16 },
+    /// Command class slot is not in Table A-1
+    UnknownCommandClass(u8),
+    /// Response type slot is not in Table A-2
+    UnknownResponseType(u8),
+    /// Parameter data longer than RDM_MAX_PDL cannot be framed
+    PdTooLong(usize),
+    /// Nothing came back before the transport gave up
+    NoResponse,
+    /// A frame came back but does not answer the request (wrong source, TN, PID or command class)
+    UnexpectedResponse,
+    /// The device refused the request
+    Nack(NackReason),
+    /// The device switched to a NACK part way through an ACK_OVERFLOW sequence
+    NackDuringOverflow { reason: NackReason, received: usize },
+    /// ACK_OVERFLOW chunks added up to more than the reassembly limit
+    OverflowLimit(usize),
+    /// The device sent ACK_TIMER but its deferred response never showed up in QUEUED_MESSAGE
+    QueuedMessageTimeout,
+    /// The transport failed, as distinct from the device not answering
+    Transport(TransportError),
+    /// Discovery narrowed a collision down to one UID and still could not mute it.
+    /// Either the device ignores DISC_MUTE or two devices share the UID.
+    UnresolvableCollision(Uid),
+    /// Discovery ran past its time budget
+    DiscoveryTimeout(Duration),
+    /// RdmController gave up retrying a request after this long
+    RequestTimeout(Duration),
+    /// A broadcast was asked for, but the destination only addresses one device
+    NotBroadcast(Uid),
+    /// The buffer given to encode a frame into cannot hold it
+    BufferTooSmall { needed: usize, got: usize },
+    /// A parameter data field holds more than its len or max_len allows, in bytes for a
+    /// string and entries for a repeated group
+    FieldTooLong { len: usize, max_len: usize },
+}
+
+impl fmt::Display for RdmError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RdmError::TooShort { needed, got } => write!(f, "too short: need {} bytes, got {}", needed, got),
+            RdmError::BadStartCode(sc) => write!(f, "bad start code {:02X}", sc),
+            RdmError::BadSubStartCode(sc) => write!(f, "bad sub-start code {:02X}", sc),
+            RdmError::LengthMismatch { message_length, pdl } => write!(f, "message length {} does not match PDL {}", message_length, pdl),
+            RdmError::PdlOverrun { pdl, available } => write!(f, "PDL {} overruns the {} bytes available", pdl, available),
+            RdmError::BadChecksum { expected, received } => write!(f, "bad checksum: expected {:04X}, received {:04X}", expected, received),
+            RdmError::UnknownCommandClass(cc) => write!(f, "unknown command class {:02X}", cc),
+            RdmError::UnknownResponseType(rt) => write!(f, "unknown response type {:02X}", rt),
+            RdmError::PdTooLong(len) => write!(f, "{} bytes of parameter data exceeds the {} byte limit", len, RDM_MAX_PDL),
+            RdmError::NoResponse => write!(f, "no response"),
+            RdmError::UnexpectedResponse => write!(f, "response does not match the request"),
+            RdmError::Nack(reason) => write!(f, "NACK: {}", reason),
+            RdmError::NackDuringOverflow { reason, received } => write!(f, "NACK after {} bytes of ACK_OVERFLOW: {}", received, reason),
+            RdmError::OverflowLimit(limit) => write!(f, "ACK_OVERFLOW response exceeds the {} byte limit", limit),
+            RdmError::QueuedMessageTimeout => write!(f, "deferred response did not arrive in QUEUED_MESSAGE"),
+            RdmError::Transport(e) => write!(f, "transport error: {}", e),
+            RdmError::UnresolvableCollision(uid) => write!(f, "unresolvable collision at UID {}", uid),
+            RdmError::DiscoveryTimeout(budget) => write!(f, "discovery ran past its {:?} budget", budget),
+            RdmError::RequestTimeout(timeout) => write!(f, "no usable response within {:?}", timeout),
+            RdmError::NotBroadcast(uid) => write!(f, "{} is not a broadcast or vendorcast address", uid),
+            RdmError::BufferTooSmall { needed, got } => write!(f, "frame needs {} bytes, buffer has {}", needed, got),
+            RdmError::FieldTooLong { len, max_len } => write!(f, "field of length {} exceeds its maximum of {}", len, max_len),
+        }
+    }
+}
+
+impl std::error::Error for RdmError {}
+
+impl From<TransportError> for RdmError {
+    fn from(e: TransportError) -> Self {
+        RdmError::Transport(e)
+    }
+}
+
+/// Additive 16 bit checksum used by RDM frames
+pub fn rdm_checksum(data: &[u8]) -> u16 {
+    data.iter().fold(0u16, |sum, byte| sum.wrapping_add(*byte as u16))
+}
+
 /// Packet Structure
 /// This includes all fields (including ones can calculate)
 /// This allows us to use it as a container format for incoming packets to validate them
@@ -366,66 +1457,113 @@ pub struct Pkt {
 
 impl fmt::Debug for Pkt {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{:02X} {:02X}\nLength: {}\nDest: {}\nSource: {}\nTN: {:02X} RT: {:02X} MC: {:02X}\nSD: {}\nCC: {:02X} PID: {:04X} PDL: {}\nPD: {:?}\nChecksum: {:04X}", 
+        let cc = match self.command_class() {
+            Ok(cc) => cc.to_string(),
+            Err(_) => "?".to_string()
+        };
+
+        write!(f, "{:02X} {:02X}\nLength: {}\nDest: {}\nSource: {}\nTN: {:02X} RT: {:02X} MC: {:02X}\nSD: {}\nCC: {:02X} ({}) PID: {:04X} ({}) PDL: {}\nPD: {:?}\nChecksum: {:04X}", 
         self.start, self.substart, 
         self.message_length,
         self.destination,
         self.source,
         self.tn, self.port_or_response_type, self.message_count,
         self.subdevice,
-        self.cc, self.pid, self.pdl,
+        self.cc, cc, self.pid, self.parameter_id(), self.pdl,
         self.pd,
         self.checksum
     )
     }
 }
 
+impl Default for Pkt {
+    fn default() -> Self {
+        Pkt::new()
+    }
+}
+
 impl Pkt {
     pub fn new() -> Pkt {
         Pkt { start: SC_RDM, substart: SC_SUB_MESSAGE, message_length: 0, destination: Uid::new(0,0), source: Uid::new(0,0), tn: 0, 
             port_or_response_type: 0, message_count: 0, subdevice: 0, cc: 0, pid: 0, pdl: 0, pd: Vec::new(), checksum: 0 }
     }
 
-    pub fn set_checksum(&mut self) -> u16 {
-        let mut checksum : u16 = 0;
-
-        // let mut temp_pkt = self.clone();
+    pub fn command_class(&self) -> Result<CommandClass, RdmError> {
+        CommandClass::try_from(self.cc)
+    }
 
-        self.checksum = 0;
+    /// Only meaningful on responses; requests carry a port ID in the same slot
+    pub fn response_type(&self) -> Result<ResponseType, RdmError> {
+        ResponseType::try_from(self.port_or_response_type)
+    }
 
-        let data = self.serialize();
+    pub fn parameter_id(&self) -> ParameterId {
+        ParameterId::from(self.pid)
+    }
 
-        for byte in data {
-            checksum = checksum.overflowing_add(byte as u16).0;
-        }
+    pub fn set_checksum(&mut self) -> u16 {
+        self.checksum = self.calculate_checksum();
+        self.checksum
+    }
 
-        self.checksum = checksum;
+    pub fn test_checksum(&self) -> bool {
+        self.checksum == self.calculate_checksum()
+    }
 
-        return checksum;
+    /// Sums the fields as they would be serialized, without building the frame
+    fn calculate_checksum(&self) -> u16 {
+        let header = [self.start, self.substart, self.message_length, self.tn, self.port_or_response_type, self.message_count, self.cc, self.pdl];
 
+        rdm_checksum(&header)
+            .wrapping_add(rdm_checksum(&self.destination.uid_serialize()))
+            .wrapping_add(rdm_checksum(&self.source.uid_serialize()))
+            .wrapping_add(rdm_checksum(&self.subdevice.to_be_bytes()))
+            .wrapping_add(rdm_checksum(&self.pid.to_be_bytes()))
+            .wrapping_add(rdm_checksum(&self.pd))
     }
 
-    pub fn test_checksum(&self) -> bool {
-        let mut checksum : u16 = 0;
-        let mut test_packet = self.clone();
-        test_packet.checksum = 0;
-
-        let data = test_packet.serialize();
+    /// Length of the frame encode_into will write, including the checksum
+    pub fn encoded_len(&self) -> usize {
+        RDM_HEADER_LENGTH + self.pd.len() + 2
+    }
 
-        for byte in data {
-            checksum = checksum.overflowing_add(byte as u16).0;
+    /// Writes the frame into buf and returns the number of bytes used.
+    /// Message length and PDL are taken from pd and the checksum is summed as the bytes are
+    /// written, so neither needs to be set beforehand and the packet itself is not modified.
+    /// Fails with PdTooLong if pd cannot be framed, or BufferTooSmall if buf is shorter than
+    /// encoded_len().
+    pub fn encode_into(&self, buf: &mut [u8]) -> Result<usize, RdmError> {
+        if self.pd.len() > RDM_MAX_PDL {
+            return Err(RdmError::PdTooLong(self.pd.len()));
         }
 
-        self.checksum == checksum
-
+        let message_length = RDM_HEADER_LENGTH + self.pd.len();
+        let got = buf.len();
+        let buf = buf.get_mut(..message_length + 2).ok_or(RdmError::BufferTooSmall { needed: message_length + 2, got })?;
+
+        buf[0] = self.start;
+        buf[1] = self.substart;
+        buf[2] = message_length as u8;
+        buf[3..9].copy_from_slice(&self.destination.uid_serialize());
+        buf[9..15].copy_from_slice(&self.source.uid_serialize());
+        buf[15] = self.tn;
+        buf[16] = self.port_or_response_type;
+        buf[17] = self.message_count;
+        buf[18..20].copy_from_slice(&self.subdevice.to_be_bytes());
+        buf[20] = self.cc;
+        buf[21..23].copy_from_slice(&self.pid.to_be_bytes());
+        buf[23] = self.pd.len() as u8;
+        buf[RDM_HEADER_LENGTH..message_length].copy_from_slice(&self.pd);
+
+        let checksum = rdm_checksum(&buf[..message_length]);
+        buf[message_length..].copy_from_slice(&checksum.to_be_bytes());
+
+        Ok(message_length + 2)
     }
 
     pub fn serialize(&self) -> Vec<u8> {
-        let mut data = Vec::new();
+        let mut data = vec![self.start, self.substart, self.message_length];
 
-        data.push(self.start);
-        data.push(self.substart);
-        data.push(self.message_length);
         data.extend(self.destination.uid_serialize());
         data.extend(self.source.uid_serialize());
         data.push(self.tn);
@@ -438,114 +1576,412 @@ impl Pkt {
         data.extend(self.pd.as_slice());
         data.extend(self.checksum.to_be_bytes());
 
-        return data;
+        data
     }
 
-    pub fn deserialize(data: Vec<u8>) -> Option<Pkt> {
-        
-        // debug!("deserialize({:?})",data);
-
-        let mut ret = Pkt::new();
+    /// Decodes a frame starting at the start code into an owned packet.
+    /// See PktRef::new for the checks applied; bytes after the checksum are ignored.
+    pub fn deserialize(data: Vec<u8>) -> Result<Pkt, RdmError> {
+        Ok(PktRef::new(&data)?.to_pkt())
+    }
 
-        if data.len() < 14 {
-            error!("Not enough data, need at least 14 bytes, have {}",data.len());
-            return None;
-            
+    /// Sets message length from pdl.  Fails with PdTooLong if pdl is past RDM_MAX_PDL, since
+    /// the length would not fit in its slot.
+    pub fn set_message_length(&mut self) -> Result<u8, RdmError> {
+        if self.pdl as usize > RDM_MAX_PDL {
+            return Err(RdmError::PdTooLong(self.pdl as usize));
         }
 
-        ret.start = data[0];
-        ret.substart = data[1];
-        ret.message_length = data[2];
+        self.message_length = self.pdl + RDM_HEADER_LENGTH as u8;
+        Ok(self.message_length)
+    }
+
+}
+
+/// Borrowed view of a validated frame
+/// Accessors read straight out of the receive buffer, so decoding a frame does not allocate.
+#[derive(Clone, Copy)]
+pub struct PktRef<'a> {
+    data: &'a [u8]
+}
 
-        if data.len() < (ret.message_length as usize) + 2 {
-            error!("data.len was incorrect got {} wanted {}",data.len(),ret.message_length+2);
-            return None; // May want some more useful errors later
+impl<'a> PktRef<'a> {
+    /// Validates the frame at the start of data.
+    /// Every length field is checked against the buffer before it is used, so malformed input
+    /// produces an RdmError rather than a panic.  Bytes after the checksum are ignored.
+    pub fn new(data: &'a [u8]) -> Result<PktRef<'a>, RdmError> {
+        if data.len() < RDM_MIN_FRAME_LENGTH {
+            return Err(RdmError::TooShort { needed: RDM_MIN_FRAME_LENGTH, got: data.len() });
         }
 
-        ret.destination = Uid::from_bytes(&data[3..9]);
-        ret.source = Uid::from_bytes(&data[9..15]);
-
-        ret.tn = data[15];
-        ret.port_or_response_type = data[16];
-        ret.message_count = data[17];
-        ret.subdevice = u16::from_be_bytes(data[18..20].try_into().unwrap());
-        ret.cc = data[20];
-        ret.pid = u16::from_be_bytes(data[21..23].try_into().unwrap());
-        ret.pdl = data[23];
-        if ret.pdl > 0 {
-            ret.pd.extend(&data[24..((ret.pdl+24) as usize)]);
+        if data[0] != SC_RDM {
+            return Err(RdmError::BadStartCode(data[0]));
         }
-        ret.checksum = u16::from_be_bytes(data[(ret.message_length as usize)..((ret.message_length+2) as usize)].try_into().unwrap());
 
-        return Some(ret);
+        if data[1] != SC_SUB_MESSAGE {
+            return Err(RdmError::BadSubStartCode(data[1]));
+        }
+
+        let message_length = data[2] as usize;
+        let pdl = data[23] as usize;
+
+        if message_length < RDM_HEADER_LENGTH {
+            return Err(RdmError::LengthMismatch { message_length: data[2], pdl: data[23] });
+        }
+
+        if data.len() < message_length + 2 {
+            return Err(RdmError::TooShort { needed: message_length + 2, got: data.len() });
+        }
+
+        if RDM_HEADER_LENGTH + pdl > message_length {
+            return Err(RdmError::PdlOverrun { pdl: data[23], available: message_length - RDM_HEADER_LENGTH });
+        }
+
+        if RDM_HEADER_LENGTH + pdl != message_length {
+            return Err(RdmError::LengthMismatch { message_length: data[2], pdl: data[23] });
+        }
+
+        let checksum = u16::from_be_bytes([data[message_length], data[message_length + 1]]);
+        let calculated = rdm_checksum(&data[..message_length]);
+
+        if checksum != calculated {
+            return Err(RdmError::BadChecksum { expected: calculated, received: checksum });
+        }
+
+        Ok(PktRef { data: &data[..message_length + 2] })
+    }
+
+    /// The frame including the checksum, without any trailing bytes
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.data
+    }
+
+    pub fn message_length(&self) -> u8 {
+        self.data[2]
+    }
+
+    pub fn destination(&self) -> Uid {
+        Uid::from_array(self.data[3..9].try_into().unwrap())
+    }
+
+    pub fn source(&self) -> Uid {
+        Uid::from_array(self.data[9..15].try_into().unwrap())
     }
 
-    pub fn set_message_length(&mut self) -> u8 {
-        self.message_length = self.pdl + 24;
-        self.message_length
+    pub fn tn(&self) -> u8 {
+        self.data[15]
     }
 
+    pub fn port_or_response_type(&self) -> u8 {
+        self.data[16]
+    }
+
+    pub fn message_count(&self) -> u8 {
+        self.data[17]
+    }
+
+    pub fn subdevice(&self) -> u16 {
+        u16::from_be_bytes([self.data[18], self.data[19]])
+    }
+
+    pub fn cc(&self) -> u8 {
+        self.data[20]
+    }
+
+    pub fn pid(&self) -> u16 {
+        u16::from_be_bytes([self.data[21], self.data[22]])
+    }
+
+    pub fn pdl(&self) -> u8 {
+        self.data[23]
+    }
+
+    pub fn command_class(&self) -> Result<CommandClass, RdmError> {
+        CommandClass::try_from(self.cc())
+    }
+
+    /// Only meaningful on responses; requests carry a port ID in the same slot
+    pub fn response_type(&self) -> Result<ResponseType, RdmError> {
+        ResponseType::try_from(self.port_or_response_type())
+    }
+
+    pub fn parameter_id(&self) -> ParameterId {
+        ParameterId::from(self.pid())
+    }
+
+    pub fn pd(&self) -> &'a [u8] {
+        &self.data[RDM_HEADER_LENGTH..self.data.len() - 2]
+    }
+
+    pub fn checksum(&self) -> u16 {
+        let len = self.data.len();
+        u16::from_be_bytes([self.data[len - 2], self.data[len - 1]])
+    }
+
+    /// Copies the frame into an owned Pkt
+    pub fn to_pkt(&self) -> Pkt {
+        Pkt {
+            start: self.data[0],
+            substart: self.data[1],
+            message_length: self.message_length(),
+            destination: self.destination(),
+            source: self.source(),
+            tn: self.tn(),
+            port_or_response_type: self.port_or_response_type(),
+            message_count: self.message_count(),
+            subdevice: self.subdevice(),
+            cc: self.cc(),
+            pid: self.pid(),
+            pdl: self.pdl(),
+            pd: self.pd().to_vec(),
+            checksum: self.checksum()
+        }
+    }
 }
 
-/// DiscoveryResponse packet data
-/// None == no response received
-/// One == a single UID was received with a valid checksum
-/// Some == Data was received, but it was invalid 
-pub enum DiscoveryResponse {
-    None,
-    One(Uid),
-    Some
+impl fmt::Debug for PktRef<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(&self.to_pkt(), f)
+    }
 }
 
-impl PartialEq for Uid {
-    fn eq(&self, other: &Self) -> bool {
-        self.dev == other.dev && self.mfg == other.mfg
+/// Builds a Pkt with PDL, message length and checksum derived from the parameter data
+/// Start with RdmRequest for requests or PktBuilder::new for anything else.
+#[derive(Clone, Debug)]
+pub struct PktBuilder {
+    pkt: Pkt
+}
+
+impl PktBuilder {
+    pub fn new(cc: CommandClass, destination: Uid, pid: impl Into<u16>) -> PktBuilder {
+        let mut pkt = Pkt::new();
+
+        pkt.cc = cc.into();
+        pkt.destination = destination;
+        pkt.pid = pid.into();
+
+        PktBuilder { pkt }
+    }
+
+    pub fn source(mut self, source: Uid) -> PktBuilder {
+        self.pkt.source = source;
+        self
+    }
+
+    pub fn tn(mut self, tn: u8) -> PktBuilder {
+        self.pkt.tn = tn;
+        self
+    }
+
+    /// Port ID for requests, shares a slot with response_type
+    pub fn port_id(mut self, port_id: u8) -> PktBuilder {
+        self.pkt.port_or_response_type = port_id;
+        self
+    }
+
+    /// Response type for responses, shares a slot with port_id
+    pub fn response_type(mut self, response_type: ResponseType) -> PktBuilder {
+        self.pkt.port_or_response_type = response_type.into();
+        self
+    }
+
+    pub fn message_count(mut self, message_count: u8) -> PktBuilder {
+        self.pkt.message_count = message_count;
+        self
+    }
+
+    pub fn sub_device(mut self, sub_device: u16) -> PktBuilder {
+        self.pkt.subdevice = sub_device;
+        self
+    }
+
+    pub fn pd(mut self, pd: &[u8]) -> PktBuilder {
+        self.pkt.pd = pd.to_vec();
+        self
+    }
+
+    /// Fills in PDL, message length and checksum.
+    /// Fails with PdTooLong rather than wrapping the length slots.
+    pub fn build(mut self) -> Result<Pkt, RdmError> {
+        if self.pkt.pd.len() > RDM_MAX_PDL {
+            return Err(RdmError::PdTooLong(self.pkt.pd.len()));
+        }
+
+        self.pkt.pdl = self.pkt.pd.len() as u8;
+        self.pkt.set_message_length()?;
+        self.pkt.set_checksum();
+
+        Ok(self.pkt)
     }
 }
 
-#[derive(Debug)]
-pub struct DeviceLabelPD {
-    pub device_label : String
+impl PktBuilder {
+    /// Starts a reply to request: addresses swapped, TN, sub-device and PID copied and the
+    /// matching response command class selected.  Message count is left at 0 for the
+    /// responder to fill in if it has queued messages.
+    pub fn response_to(request: &Pkt) -> Result<PktBuilder, RdmError> {
+        let cc = request.command_class()?
+            .response()
+            .ok_or(RdmError::UnknownCommandClass(request.cc))?;
+
+        Ok(PktBuilder::new(cc, request.source, request.pid)
+            .source(request.destination)
+            .tn(request.tn)
+            .sub_device(request.subdevice))
+    }
 }
 
-impl DeviceLabelPD {
-    pub fn serialize(&self) -> Vec<u8> {
-        let mut rv = Vec::new();
+impl Pkt {
+    /// RESPONSE_TYPE_ACK carrying pd.  message_count is how many messages the responder has
+    /// queued for QUEUED_MESSAGE, as in the rest of these helpers.
+    pub fn ack(request: &Pkt, pd: &[u8], message_count: u8) -> Result<Pkt, RdmError> {
+        PktBuilder::response_to(request)?
+            .response_type(ResponseType::Ack)
+            .message_count(message_count)
+            .pd(pd)
+            .build()
+    }
 
-        rv.extend(self.device_label.as_bytes());
+    /// RESPONSE_TYPE_ACK_TIMER; the estimate is sent in tenths of a second, rounded up
+    pub fn ack_timer(request: &Pkt, estimate: Duration, message_count: u8) -> Result<Pkt, RdmError> {
+        let tenths = estimate.as_millis().div_ceil(100).min(u16::MAX as u128) as u16;
 
-        return rv;
+        PktBuilder::response_to(request)?
+            .response_type(ResponseType::AckTimer)
+            .message_count(message_count)
+            .pd(&tenths.to_be_bytes())
+            .build()
+    }
 
+    /// RESPONSE_TYPE_NACK_REASON carrying the reason code
+    pub fn nack(request: &Pkt, reason: NackReason, message_count: u8) -> Result<Pkt, RdmError> {
+        PktBuilder::response_to(request)?
+            .response_type(ResponseType::NackReason)
+            .message_count(message_count)
+            .pd(&reason.encode())
+            .build()
     }
 
-    pub fn deserialize(data: Vec<u8>) -> Option<DeviceLabelPD> {
+    /// RESPONSE_TYPE_ACK_OVERFLOW carrying one chunk of a response that does not fit in a
+    /// single frame.  The final chunk is sent with ack.
+    pub fn ack_overflow(request: &Pkt, chunk: &[u8], message_count: u8) -> Result<Pkt, RdmError> {
+        PktBuilder::response_to(request)?
+            .response_type(ResponseType::AckOverflow)
+            .message_count(message_count)
+            .pd(chunk)
+            .build()
+    }
+}
 
-        let mut pd = DeviceLabelPD::new();
+/// What a response frame says, decoded in one step
+/// AckOverflow carries the raw chunk since a partial response cannot be decoded on its own;
+/// RdmController reassembles them.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RdmResponse<T> {
+    Ack(T),
+    /// The device will have the answer in QUEUED_MESSAGE after roughly this long
+    AckTimer(Duration),
+    Nack(NackReason),
+    AckOverflow(Vec<u8>)
+}
 
-        if data.len() == 0 {
-            return Some(pd);
+impl<T> RdmResponse<T> {
+    /// Decodes response, handing ACK parameter data to decode
+    pub fn decode_with<F: FnOnce(Vec<u8>) -> Result<T, RdmError>>(response: &Pkt, decode: F) -> Result<RdmResponse<T>, RdmError> {
+        if !response.command_class()?.is_response() {
+            return Err(RdmError::UnexpectedResponse);
         }
 
-        let mut last_index = data.len();
+        Ok(match response.response_type()? {
+            ResponseType::Ack => RdmResponse::Ack(decode(response.pd.clone())?),
+            ResponseType::AckTimer => RdmResponse::AckTimer(decode_ack_timer(&response.pd)?),
+            ResponseType::NackReason => RdmResponse::Nack(NackReason::decode(&response.pd)?),
+            ResponseType::AckOverflow => RdmResponse::AckOverflow(response.pd.clone())
+        })
+    }
 
-        for i in 0..data.len() {
-            if data[i] == 0x00 { // if it's a null terminated string, truncate here.
-                last_index = i;
-                break;
-            }
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> RdmResponse<U> {
+        match self {
+            RdmResponse::Ack(value) => RdmResponse::Ack(f(value)),
+            RdmResponse::AckTimer(delay) => RdmResponse::AckTimer(delay),
+            RdmResponse::Nack(reason) => RdmResponse::Nack(reason),
+            RdmResponse::AckOverflow(chunk) => RdmResponse::AckOverflow(chunk)
         }
-        
-        pd.device_label = str::from_utf8(&data[0..last_index]).unwrap_or("").to_string();
-        
-        return Some(pd);
     }
+}
+
+impl<T: PdCodec> RdmResponse<T> {
+    /// Decodes response, and the ACK parameter data as T
+    pub fn decode(response: &Pkt) -> Result<RdmResponse<T>, RdmError> {
+        RdmResponse::decode_with(response, T::deserialize)
+    }
+}
+
+impl RdmResponse<Vec<u8>> {
+    /// Decodes response, leaving the ACK parameter data as it came
+    pub fn decode_raw(response: &Pkt) -> Result<RdmResponse<Vec<u8>>, RdmError> {
+        RdmResponse::decode_with(response, Ok)
+    }
+}
+
+/// Entry points for building requests
+/// RdmRequest::get(uid, DEVICE_INFO).source(my_uid).sub_device(1).build()
+pub struct RdmRequest;
+
+impl RdmRequest {
+    pub fn discovery(destination: Uid, pid: impl Into<u16>) -> PktBuilder {
+        PktBuilder::new(CommandClass::DiscoveryCommand, destination, pid).port_id(0x01)
+    }
+
+    pub fn get(destination: Uid, pid: impl Into<u16>) -> PktBuilder {
+        PktBuilder::new(CommandClass::GetCommand, destination, pid).port_id(0x01)
+    }
+
+    pub fn set(destination: Uid, pid: impl Into<u16>) -> PktBuilder {
+        PktBuilder::new(CommandClass::SetCommand, destination, pid).port_id(0x01)
+    }
+}
+
+/// DiscoveryResponse packet data
+/// None == no response received
+/// One == a single UID was received with a valid checksum
+/// Some == Data was received, but it was invalid 
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DiscoveryResponse {
+    None,
+    One(Uid),
+    Some
+}
+
+/// Reads an ASCII string field, stopping at the first null if the sender terminated it.
+/// Invalid UTF-8 decodes as an empty string.
+fn pd_string(data: &[u8]) -> String {
+    let last_index = data.iter().position(|byte| *byte == 0x00).unwrap_or(data.len());
+
+    str::from_utf8(&data[0..last_index]).unwrap_or("").to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, RdmParameter)]
+#[rdm(pid = DEVICE_LABEL)]
+pub struct DeviceLabelPD {
+    #[rdm(max_len = 32)]
+    pub device_label : String
+}
 
+impl Default for DeviceLabelPD {
+    fn default() -> Self {
+        DeviceLabelPD::new()
+    }
+}
+
+impl DeviceLabelPD {
     pub fn new() -> DeviceLabelPD {
         DeviceLabelPD { device_label: "".to_string() }
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, RdmParameter)]
+#[rdm(pid = DEVICE_INFO, get)]
 pub struct DeviceInfoPD {
     pub rdm_protocol_version : u16,
     pub device_model_id : u16,
@@ -558,46 +1994,13 @@ pub struct DeviceInfoPD {
     pub sensor_count : u8
 }
 
-impl DeviceInfoPD {
-    pub fn serialize(&self) -> Vec<u8> {
-        let mut rv = Vec::new();
-
-        rv.extend(self.rdm_protocol_version.to_be_bytes());
-        rv.extend(self.device_model_id.to_be_bytes());
-        rv.extend(self.product_category.to_be_bytes());
-        rv.extend(self.software_version_id.to_be_bytes());
-        rv.extend(self.dmx_footprint.to_be_bytes());
-        rv.extend(self.dmx_personality.to_be_bytes());
-        rv.extend(self.dmx_start_address.to_be_bytes());
-        rv.extend(self.sub_device_count.to_be_bytes());
-
-        rv.push(self.sensor_count);
-        
-        return rv;
-    }
-
-    pub fn deserialize(data : Vec<u8>) -> Option<DeviceInfoPD> {
-        if data.len() < 0x13 {
-            return None;
-        }
-
-        let mut pd = DeviceInfoPD::new();
-
-        pd.rdm_protocol_version = u16::from_be_bytes(data[0..2].try_into().unwrap());
-        pd.device_model_id = u16::from_be_bytes(data[2..4].try_into().unwrap());
-        pd.product_category = u16::from_be_bytes(data[4..6].try_into().unwrap());
-        pd.software_version_id = u32::from_be_bytes(data[6..10].try_into().unwrap());
-        pd.dmx_footprint = u16::from_be_bytes(data[10..12].try_into().unwrap());
-        pd.dmx_personality = u16::from_be_bytes(data[12..14].try_into().unwrap());
-        pd.dmx_start_address = u16::from_be_bytes(data[14..16].try_into().unwrap());
-        pd.sub_device_count = u16::from_be_bytes(data[16..18].try_into().unwrap());
-
-        pd.sensor_count = data[18];
-
-        return Some(pd);
-
+impl Default for DeviceInfoPD {
+    fn default() -> Self {
+        DeviceInfoPD::new()
     }
+}
 
+impl DeviceInfoPD {
     pub fn new() -> DeviceInfoPD {
         DeviceInfoPD { 
             rdm_protocol_version: 0, 
@@ -615,7 +2018,8 @@ impl DeviceInfoPD {
 
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, RdmParameter)]
+#[rdm(pid = SENSOR_DEFINITION, get, get_request = u8)]
 pub struct SensorDefinitionPD {
     pub id : u8,
     pub sensor_type : u8,
@@ -626,69 +2030,17 @@ pub struct SensorDefinitionPD {
     pub normal_min : i16,
     pub normal_max : i16,
     pub recorded_value : u8,
+    #[rdm(max_len = 32)]
     pub description : String
 }
 
-impl SensorDefinitionPD {
-    pub fn serialize(&self) -> Vec<u8> {
-        let mut rv = Vec::new();
-
-        rv.push(self.id);
-        rv.push(self.sensor_type);
-        rv.push(self.unit);
-        rv.push(self.prefix);
-
-        rv.extend(self.range_min.to_be_bytes());
-        rv.extend(self.range_max.to_be_bytes());
-        rv.extend(self.normal_min.to_be_bytes());
-        rv.extend(self.normal_max.to_be_bytes());
-
-        rv.push(self.recorded_value);
-        
-        rv.extend(self.description.as_bytes());
-
-        return rv;
-    }
-
-    pub fn deserialize(data : Vec<u8>) -> Option<SensorDefinitionPD> {
-        if data.len() < 0x0D {
-            return None;
-        }
-
-        let mut pd = SensorDefinitionPD::new();
-
-        pd.id = data[0];
-        pd.sensor_type = data[1];
-        pd.unit = data[2];
-        pd.prefix = data[3];
-
-        pd.range_min = i16::from_be_bytes(data[4..6].try_into().unwrap());
-        pd.range_max = i16::from_be_bytes(data[6..8].try_into().unwrap());
-        pd.normal_min = i16::from_be_bytes(data[8..10].try_into().unwrap());
-        pd.normal_max = i16::from_be_bytes(data[10..12].try_into().unwrap());
-        
-        pd.recorded_value = data[12];
-
-        if data.len() > 0x0D {
-
-            let mut last_index = data.len();
-
-            for i in 13..data.len() {
-                if data[i] == 0x00 { // if it's a null terminated string, truncate here.
-                    last_index = i;
-                    break;
-                }
-            }
-
-            if last_index > 13 {
-                pd.description = str::from_utf8(&data[13..last_index]).unwrap_or("").to_string();
-            }
-        }
-
-        return Some(pd);
-
+impl Default for SensorDefinitionPD {
+    fn default() -> Self {
+        SensorDefinitionPD::new()
     }
+}
 
+impl SensorDefinitionPD {
     pub fn new() -> SensorDefinitionPD {
         SensorDefinitionPD { 
             id: 0, 
@@ -707,7 +2059,8 @@ impl SensorDefinitionPD {
 
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq, RdmParameter)]
+#[rdm(pid = SENSOR_VALUE, get_request = u8, set_request = u8, set_response = Self)]
 pub struct SensorValuePD {
     pub id : u8,
     pub present : i16,
@@ -716,44 +2069,247 @@ pub struct SensorValuePD {
     pub recorded: i16
 }
 
+impl Default for SensorValuePD {
+    fn default() -> Self {
+        SensorValuePD::new()
+    }
+}
+
 impl SensorValuePD {
     pub fn new() -> SensorValuePD {
         SensorValuePD { id: 0, present: 0, lowest: 0, highest: 0, recorded: 0 }
     }
+}
 
+#[derive(Debug, Clone, Default, PartialEq, RdmParameter)]
+#[rdm(pid = PROXIED_DEVICE_COUNT, get)]
+pub struct ProxiedDeviceCountPD {
+    pub device_count : u16,
+    /// Set when the list has changed since PROXIED_DEVICES was last read
+    pub list_change : bool
+}
+
+/// Usually reassembled from ACK_OVERFLOW; a trailing partial UID is dropped
+#[derive(Debug, Clone, Default, PartialEq, RdmParameter)]
+#[rdm(pid = PROXIED_DEVICES, get)]
+pub struct ProxiedDevicesPD {
+    pub uids : Vec<Uid>
+}
+
+/// Control field bits of a DISC_MUTE / DISC_UN_MUTE response
+pub const CONTROL_FIELD_MANAGED_PROXY                 : u16 = 0x0001;
+pub const CONTROL_FIELD_SUB_DEVICE                    : u16 = 0x0002;
+pub const CONTROL_FIELD_BOOT_LOADER                   : u16 = 0x0004;
+pub const CONTROL_FIELD_PROXIED_DEVICE                : u16 = 0x0008;
+
+/// Parameter data of a DISC_MUTE / DISC_UN_MUTE response
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct MuteResponse {
+    pub control_field : u16,
+    /// Only sent by devices with more than one responder port; every port of the device
+    /// reports the same binding UID
+    pub binding_uid : Option<Uid>
+}
+
+impl MuteResponse {
     pub fn serialize(&self) -> Vec<u8> {
         let mut rv = Vec::new();
 
-        rv.push(self.id);
-        rv.extend(self.present.to_be_bytes());
-        rv.extend(self.lowest.to_be_bytes());
-        rv.extend(self.highest.to_be_bytes());
-        rv.extend(self.recorded.to_be_bytes());
+        rv.extend(self.control_field.to_be_bytes());
+
+        if let Some(uid) = self.binding_uid {
+            rv.extend(uid.uid_serialize());
+        }
 
-        return rv;
+        rv
     }
 
-    pub fn deserialize(data : Vec<u8>) -> Option<SensorValuePD> {
-        if data.len() < 9 {
-            return None;
+    pub fn deserialize(data : Vec<u8>) -> Result<MuteResponse, RdmError> {
+        if data.len() < 2 {
+            return Err(RdmError::TooShort { needed: 2, got: data.len() });
         }
 
-        let mut pd = SensorValuePD::new();
+        let control_field = u16::from_be_bytes(data[0..2].try_into().unwrap());
 
-        pd.id = data[0];
+        let binding_uid = if data.len() >= 8 {
+            Some(Uid::from_bytes(&data[2..8])?)
+        } else {
+            None
+        };
 
-        pd.present = i16::from_be_bytes(data[1..3].try_into().unwrap());
-        pd.lowest = i16::from_be_bytes(data[3..5].try_into().unwrap());
-        pd.highest = i16::from_be_bytes(data[5..7].try_into().unwrap());
-        pd.recorded = i16::from_be_bytes(data[7..9].try_into().unwrap());
+        Ok(MuteResponse { control_field, binding_uid })
+    }
 
-        return Some(pd);
+    /// The device is a proxy; fetch its children with PROXIED_DEVICES
+    pub fn managed_proxy(&self) -> bool {
+        self.control_field & CONTROL_FIELD_MANAGED_PROXY != 0
+    }
 
+    pub fn sub_devices(&self) -> bool {
+        self.control_field & CONTROL_FIELD_SUB_DEVICE != 0
     }
 
+    /// The device is in its boot-loader and can only be used to update firmware
+    pub fn boot_loader(&self) -> bool {
+        self.control_field & CONTROL_FIELD_BOOT_LOADER != 0
+    }
 
+    /// A proxy is answering on behalf of the device
+    pub fn proxied_device(&self) -> bool {
+        self.control_field & CONTROL_FIELD_PROXIED_DEVICE != 0
+    }
+}
+
+/// Parameter data as it goes on the wire, whether request or response
+pub trait PdCodec: Sized {
+    /// Fails with FieldTooLong if a field holds more than the wire format allows
+    fn serialize(&self) -> Result<Vec<u8>, RdmError>;
+
+    fn deserialize(data: Vec<u8>) -> Result<Self, RdmError>;
+}
+
+/// No parameter data.  Anything received is ignored.
+impl PdCodec for () {
+    fn serialize(&self) -> Result<Vec<u8>, RdmError> {
+        Ok(Vec::new())
+    }
+
+    fn deserialize(_data: Vec<u8>) -> Result<Self, RdmError> {
+        Ok(())
+    }
+}
+
+/// A single byte, e.g. the sensor number of SENSOR_VALUE
+impl PdCodec for u8 {
+    fn serialize(&self) -> Result<Vec<u8>, RdmError> {
+        Ok(vec![*self])
+    }
+
+    fn deserialize(data: Vec<u8>) -> Result<Self, RdmError> {
+        data.first().copied().ok_or(RdmError::TooShort { needed: 1, got: 0 })
+    }
+}
+
+impl PdCodec for u16 {
+    fn serialize(&self) -> Result<Vec<u8>, RdmError> {
+        Ok(self.to_be_bytes().to_vec())
+    }
+
+    fn deserialize(data: Vec<u8>) -> Result<Self, RdmError> {
+        match data.get(0..2) {
+            Some(bytes) => Ok(u16::from_be_bytes([bytes[0], bytes[1]])),
+            None => Err(RdmError::TooShort { needed: 2, got: data.len() })
+        }
+    }
+}
+
+/// The request and response types of a command class a PID does not support.
+/// It has no values, so the controller cannot be asked to send one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Unsupported {}
+
+impl PdCodec for Unsupported {
+    fn serialize(&self) -> Result<Vec<u8>, RdmError> {
+        match *self {}
+    }
+
+    fn deserialize(_data: Vec<u8>) -> Result<Self, RdmError> {
+        Err(RdmError::Nack(NackReason::UnsupportedCommandClass))
+    }
+}
+
+/// Which of GET and SET a PID supports, as in the command class field of PARAMETER_DESCRIPTION
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ParameterCommandClass {
+    Get,
+    Set,
+    GetSet
+}
+
+impl ParameterCommandClass {
+    pub fn supports(self, cc: CommandClass) -> bool {
+        matches!((self, cc),
+            (ParameterCommandClass::Get | ParameterCommandClass::GetSet, CommandClass::GetCommand)
+            | (ParameterCommandClass::Set | ParameterCommandClass::GetSet, CommandClass::SetCommand))
+    }
+}
+
+impl From<ParameterCommandClass> for u8 {
+    fn from(cc: ParameterCommandClass) -> u8 {
+        match cc {
+            ParameterCommandClass::Get => 0x01,
+            ParameterCommandClass::Set => 0x02,
+            ParameterCommandClass::GetSet => 0x03
+        }
+    }
+}
+
+/// A PID and the parameter data of each command class it supports, so RdmController can GET
+/// and SET it as a typed value and a responder can decode requests for it.
+/// Command classes the PID does not support use Unsupported.
+pub trait ParameterData {
+    const PID : u16;
+
+    const COMMAND_CLASS : ParameterCommandClass;
+
+    type GetRequest : PdCodec;
+    type GetResponse : PdCodec;
+    type SetRequest : PdCodec;
+    type SetResponse : PdCodec;
+}
+
+/// A GET or SET for P, decoded by a responder
+#[derive(Debug)]
+pub enum ParameterRequest<P: ParameterData> {
+    Get(P::GetRequest),
+    Set(P::SetRequest)
+}
+
+impl<P: ParameterData> ParameterRequest<P> {
+    /// Decodes request as a GET or SET of P.  The error is the NACK to answer with.
+    pub fn decode(request: &Pkt) -> Result<ParameterRequest<P>, NackReason> {
+        if request.pid != P::PID {
+            return Err(NackReason::UnknownPid);
+        }
+
+        let cc = request.command_class().map_err(|_| NackReason::UnsupportedCommandClass)?;
+
+        if !P::COMMAND_CLASS.supports(cc) {
+            return Err(NackReason::UnsupportedCommandClass);
+        }
+
+        let pd = request.pd.clone();
+
+        match cc {
+            CommandClass::GetCommand => P::GetRequest::deserialize(pd).map(ParameterRequest::Get),
+            _ => P::SetRequest::deserialize(pd).map(ParameterRequest::Set)
+        }.map_err(|_| NackReason::FormatError)
+    }
+}
+
+impl PdCodec for MuteResponse {
+    fn serialize(&self) -> Result<Vec<u8>, RdmError> {
+        Ok(MuteResponse::serialize(self))
+    }
+
+    fn deserialize(data: Vec<u8>) -> Result<Self, RdmError> {
+        MuteResponse::deserialize(data)
+    }
 }
 
+/********************************************************/
+/* Table A-4: Status Type Defines                       */
+/********************************************************/
+
+pub const STATUS_NONE                                 : u8  = 0x00;   /* Not allowed for use with GET: QUEUED_MESSAGE                 */
+pub const STATUS_GET_LAST_MESSAGE                     : u8  = 0x01;
+pub const STATUS_ADVISORY                             : u8  = 0x02;
+pub const STATUS_WARNING                              : u8  = 0x03;
+pub const STATUS_ERROR                                : u8  = 0x04;
+pub const STATUS_ADVISORY_CLEARED                     : u8  = 0x12;
+pub const STATUS_WARNING_CLEARED                      : u8  = 0x13;
+pub const STATUS_ERROR_CLEARED                        : u8  = 0x14;
+
 /********************************************************/
 /* Table A-12: Sensor Type Defines                      */
 /********************************************************/
@@ -860,263 +2416,109 @@ pub const PREFIX_YOTTA                                : u8  = 0x1A;   /* Multipl
 
 
 
-// Just compare them as 64s
-impl PartialOrd for Uid {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        if self.mfg == other.mfg { // if the mfg ids match, return the comparison between devices
-            return self.dev.partial_cmp(&other.dev);
-        } else {
-            return self.mfg.partial_cmp(&other.mfg); // otherwise just compare devices
-        }
-    }
+/// An inclusive range of UIDs, as carried in DISC_UNIQUE_BRANCH.
+/// A range whose min is above its max is empty.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct UidRange {
+    pub min : Uid,
+    pub max : Uid
 }
 
-fn do_discovery_node(f: fn(&[u8]) -> Option<Vec<u8>>,my_uid: &Uid, min: &Uid, max: &Uid, tn: &mut u8) -> DiscoveryResponse {
-
-    let mut output_pkt = Pkt::new();
-
-    output_pkt.destination = Uid::new(0xFFFF,0xFFFF_FFFF);
-    output_pkt.source = my_uid.clone();
-    
-    *tn = tn.overflowing_add(1).0;
-
-    output_pkt.tn = *tn;
-
-    output_pkt.port_or_response_type = 0x01;
-
-    output_pkt.cc = DISCOVERY_COMMAND;
-
-    output_pkt.pid = DISC_UNIQUE_BRANCH;
-
-    output_pkt.pdl = 0x0C;
-
-    output_pkt.pd.extend(min.uid_serialize());
-    output_pkt.pd.extend(max.uid_serialize());
-
-    output_pkt.set_message_length(); // sets message length from PDL
-    output_pkt.set_checksum(); // sets checksum from the whole packet.
-
-    match f(output_pkt.serialize().as_slice()) {
-        None => {
-            return DiscoveryResponse::None; // No response means no response
-        }
-        Some(data) => {
-            let mut device_uid = Uid::new(0,0);
-            let mut checksum : u16 = 0;
-            let mut checksum_calculated : u16 = 0;
-
-            if data.len() == 0 {
-                return DiscoveryResponse::None;
-            }
-
-            if data.len() >= 16 {
-
-                let mut preamble_ptr : usize = 0;
-
-                for byte in &data {
-                    preamble_ptr += 1;
-                    if *byte == 0xFE {
-                        continue;
-                    } 
-                    if *byte == 0xAA {
-                        break;
-                    }
-                }
-
-                // Make sure we have enough bytes after the preamble, if not, we have a Some.
-                if data.len() < 16+preamble_ptr {
-                    return DiscoveryResponse::Some;
-                }
-
-                device_uid.mfg = ((data[preamble_ptr] as u16 & data[preamble_ptr+1] as u16) << 8) + (data[preamble_ptr+2] as u16 & data[preamble_ptr+3] as u16);
-                device_uid.dev =    ((data[preamble_ptr+4] as u32 & data[preamble_ptr+5] as u32) << 24) 
-                                    + ((data[preamble_ptr+6] as u32 & data[preamble_ptr+7] as u32) << 16) 
-                                    + ((data[preamble_ptr+8] as u32 & data[preamble_ptr+9] as u32) << 8) 
-                                    + (data[preamble_ptr+10] as u32 & data[preamble_ptr+11] as u32);
-
-                checksum = ((data[preamble_ptr+12] as u16 & data[preamble_ptr+13] as u16) << 8) + (data[preamble_ptr+14] as u16 & data[preamble_ptr+15] as u16);
-
-                for i in preamble_ptr..(preamble_ptr+12) {
-                    checksum_calculated = checksum_calculated.overflowing_add(data[i] as u16).0;
-                }
-                
-                // If the checksum validates, we have a device, if not we return a Some.
-                if checksum == checksum_calculated {
-                    return DiscoveryResponse::One(device_uid);
-                } else {
-                    return DiscoveryResponse::Some;
-                }
-            } else {
-                return DiscoveryResponse::Some; // We got something, but don't know what it is.
-            }
-        }
+impl UidRange {
+    /// Every UID a device may have; the broadcast manufacturer 0xFFFF is never a device
+    pub const ALL : UidRange = UidRange { min: Uid { mfg: 0, dev: 0 }, max: Uid { mfg: 0x7FFF, dev: 0xFFFF_FFFF } };
 
+    pub fn new(min: Uid, max: Uid) -> UidRange {
+        UidRange { min, max }
     }
 
-
-
-}
-
-/// Runs the discovery algorithm.
-/// 0. Optionally: Unmute all (out of scope)
-/// 1. Do allcall discovery.   If no response, then return empty Vec If response, go to 2
-/// 2. Check left-hand side of tree and gather UIDs
-/// 3. Check right-hand side of tree and gather UIDs
-/// 4. Concatenate and return
-pub fn do_discovery_algo(f: fn(&[u8]) -> Option<Vec<u8>>, my_uid: &Uid, do_mute: bool, repeat_disc: bool) -> Vec<Uid> {
-
-    let mut tn : u8 = 0;
-
-    if do_mute {
-        let mut output_pkt = Pkt::new();
-
-        output_pkt.destination = Uid::new(0xFFFF,0xFFFF_FFFF);
-        output_pkt.source = my_uid.clone();
-        
-        tn = tn.overflowing_add(1).0;
-
-        output_pkt.tn = tn;
-
-        output_pkt.port_or_response_type = 0x01;
-
-        output_pkt.cc = DISCOVERY_COMMAND;
-
-        output_pkt.pid = DISC_UN_MUTE;
-
-        output_pkt.pdl = 0x00;
-
-        output_pkt.set_message_length(); // sets message length from PDL
-        output_pkt.set_checksum(); // sets checksum from the whole packet.
-
-        f(output_pkt.serialize().as_slice()); // send global unmute
-
+    /// Just uid
+    pub fn single(uid: Uid) -> UidRange {
+        UidRange { min: uid, max: uid }
     }
 
-    let min : Uid = Uid::new(0,0); 
-    let max : Uid = Uid::new(0x7FFF, 0xFFFF_FFFF);
+    /// Every device of one manufacturer.  Device ID 0xFFFFFFFF is the vendorcast address, not a device.
+    pub fn manufacturer(mfg: u16) -> UidRange {
+        UidRange { min: Uid::new(mfg, 0), max: Uid::new(mfg, 0xFFFF_FFFE) }
+    }
 
-    let mut tod = do_discovery_recursion(f, &my_uid, &min, &max, &mut tn,repeat_disc);
+    /// Reads the lower and upper bound from DISC_UNIQUE_BRANCH parameter data
+    pub fn from_bytes(bytes: &[u8]) -> Result<UidRange, RdmError> {
+        if bytes.len() < 12 {
+            return Err(RdmError::TooShort { needed: 12, got: bytes.len() });
+        }
 
-    if repeat_disc && tod.len() == 0 {
-        tod = do_discovery_recursion(f, &my_uid, &min, &max, &mut tn,repeat_disc);
+        Ok(UidRange { min: Uid::from_bytes(&bytes[0..6])?, max: Uid::from_bytes(&bytes[6..12])? })
     }
 
-    if repeat_disc && tod.len() == 0 {
-        tod = do_discovery_recursion(f, &my_uid, &min, &max, &mut tn,repeat_disc);
+    /// The DISC_UNIQUE_BRANCH parameter data for this range
+    pub fn serialize(&self) -> [u8; 12] {
+        let mut pd = [0u8; 12];
+        pd[0..6].copy_from_slice(&self.min.uid_serialize());
+        pd[6..12].copy_from_slice(&self.max.uid_serialize());
+        pd
     }
 
-    return tod;
-}
-
-/// Sends a mute message and then returns true if it got an ACK or false if it didn't
-fn send_mute_message(f: fn(&[u8]) -> Option<Vec<u8>>, my_uid: &Uid, uid: &Uid, tn : &mut u8) -> bool {
-    let mut output_pkt = Pkt::new();
-
-    output_pkt.destination = *uid;
-    output_pkt.source = my_uid.clone();
-    
-    *tn = tn.overflowing_add(1).0;
-
-    output_pkt.tn = *tn;
-
-    output_pkt.port_or_response_type = 0x01;
-
-    output_pkt.cc = DISCOVERY_COMMAND;
-
-    output_pkt.pid = DISC_MUTE;
-
-    output_pkt.pdl = 0x00;
-
-    output_pkt.set_message_length(); // sets message length from PDL
-    output_pkt.set_checksum(); // sets checksum from the whole packet.
-
-    match f(output_pkt.serialize().as_slice()) {
-        None => return false,
-        Some(data) => {
-            let response_pkt = Pkt::deserialize(data);
-
-            match response_pkt {
-                None => return false,
-                Some(data_pkt) => {
-                    if data_pkt.test_checksum() {
-                        if data_pkt.port_or_response_type == RESPONSE_TYPE_ACK 
-                        && data_pkt.source == *uid 
-                        && data_pkt.cc == DISCOVERY_COMMAND_RESPONSE 
-                        && data_pkt.pid == DISC_MUTE {
-                            return true;
-                        } else {
-                            return false;
-                        }
-                    } else {
-                        return false;
-                    }
-                }
-            }
-
+    pub fn contains(&self, uid: &Uid) -> bool {
+        self.min <= *uid && *uid <= self.max
+    }
 
+    /// Number of UIDs in the range
+    pub fn len(&self) -> u64 {
+        if self.is_empty() {
+            0
+        } else {
+            self.max.get_as_64() - self.min.get_as_64() + 1
         }
     }
 
-}
-
-fn do_discovery_recursion(f: fn(&[u8]) -> Option<Vec<u8>>, my_uid: &Uid, min: &Uid, max: &Uid, tn : &mut u8, repeat_disc: bool) -> Vec<Uid> {
-    let mut tod : Vec<Uid> = Vec::new();
+    pub fn is_empty(&self) -> bool {
+        self.min > self.max
+    }
 
-    debug!("do_discovery_recursion({},{})",min,max);
+    /// The UIDs in both ranges, or None if they do not overlap
+    pub fn intersect(&self, other: &UidRange) -> Option<UidRange> {
+        let range = UidRange::new(self.min.max(other.min), self.max.min(other.max));
 
-    match do_discovery_node(f,&my_uid,min, max, tn) {
-        DiscoveryResponse::None => { 
-            return tod; // nothing in this branch, go back up.
-        },
-        DiscoveryResponse::One(found_uid) => {
-            debug!("do_discovery_recursion: Found {}, muting it.",found_uid);
-            if send_mute_message(f,&my_uid,&found_uid,tn) {
-                tod.push(found_uid);
-                return tod; // only one thing here, return it.
-            }
-         },
-        DiscoveryResponse::Some => { 
-            // println!("Found some responders, digging deeper");
-            // need to dig deeper, so don't return.
+        if range.is_empty() {
+            None
+        } else {
+            Some(range)
         }
     }
 
-    let mid = min.get_midpoint(max);
+    /// Splits into two non-overlapping halves, min..=mid and mid+1..=max.
+    /// A range of a single UID cannot be split and returns None.
+    pub fn split_half(&self) -> Option<(UidRange, UidRange)> {
+        let bot_64 = self.min.get_as_64();
+        let top_64 = self.max.get_as_64();
 
-    // println!("Midpoint is {}", mid);
+        if bot_64 >= top_64 {
+            return None;
+        }
 
-    // Do the left branch.
-    let left_branch = do_discovery_recursion(f,&my_uid, min,&mid, tn, repeat_disc);
+        let mid_64 = bot_64 + (top_64 - bot_64) / 2;
 
-    if repeat_disc && left_branch.len() == 0 {
-        let left_branch = do_discovery_recursion(f,&my_uid, min,&mid, tn, repeat_disc);
-        if left_branch.len() == 0 {
-            let left_branch = do_discovery_recursion(f,&my_uid, min,&mid, tn, repeat_disc);
-            tod.extend(left_branch);
-        } else {
-            tod.extend(left_branch);
-        }
-    } else {
-        tod.extend(left_branch); // extend tod with the contents of left branch here even if we don't repeat discovery
+        Some((UidRange::new(self.min, Uid::new_from_64(mid_64)), UidRange::new(Uid::new_from_64(mid_64 + 1), self.max)))
     }
 
-    // now do the right branch
-    let right_branch = do_discovery_recursion(f,&my_uid, &mid,max, tn, repeat_disc);
-    if repeat_disc && right_branch.len() == 0 {
-        let right_branch = do_discovery_recursion(f,&my_uid, &mid,max, tn, repeat_disc);
-        if right_branch.len() == 0 {
-            let right_branch = do_discovery_recursion(f,&my_uid, &mid,max, tn, repeat_disc);
-            tod.extend(right_branch);
-        } else {
-            tod.extend(right_branch);
-        }
-    } else {
-        tod.extend(right_branch);
+    /// Every UID in the range, lowest first
+    pub fn iter(&self) -> impl Iterator<Item = Uid> {
+        self.into_iter()
     }
+}
 
-   
-    
-    return tod;
+impl IntoIterator for UidRange {
+    type Item = Uid;
+    type IntoIter = std::iter::Map<std::ops::RangeInclusive<u64>, fn(u64) -> Uid>;
 
+    fn into_iter(self) -> Self::IntoIter {
+        (self.min.get_as_64()..=self.max.get_as_64()).map(Uid::new_from_64 as fn(u64) -> Uid)
+    }
 }
 
+impl fmt::Display for UidRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}..={}", self.min, self.max)
+    }
+}
@@ -0,0 +1,276 @@
+//! Field by field parameter data encoding behind #[derive(RdmParameter)]
+//! Integers are big-endian, strings are ASCII without a terminator unless they are fixed width,
+//! and a variable length field takes the rest of the parameter data.  The derive writes the
+//! calls to these; they are public so that the code it generates in other crates can reach them.
+
+use crate::*;
+
+/// A value with a wire encoding that can sit in the middle of parameter data: it knows how
+/// much of the data is its own.
+pub trait PdField: Sized {
+    /// Bytes the value needs at the least; the fixed width fields of a derived struct
+    const MIN_LEN : usize;
+
+    /// The value runs to the end of the parameter data, so nothing can follow it
+    const TRAILING : bool = false;
+
+    /// Appends the value to out.  Fails with FieldTooLong if it does not fit its field.
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), RdmError>;
+
+    /// Decodes the value from the front of data and moves data past it
+    fn decode(data: &mut &[u8]) -> Result<Self, RdmError>;
+}
+
+/// Takes the next len bytes off data
+fn take<'a>(data: &mut &'a [u8], len: usize) -> Result<&'a [u8], RdmError> {
+    if data.len() < len {
+        return Err(RdmError::TooShort { needed: len, got: data.len() });
+    }
+
+    let (head, tail) = data.split_at(len);
+    *data = tail;
+
+    Ok(head)
+}
+
+macro_rules! pd_field_int {
+    ($($int:ty),* $(,)?) => {
+        $(
+            impl PdField for $int {
+                const MIN_LEN : usize = std::mem::size_of::<$int>();
+
+                fn encode(&self, out: &mut Vec<u8>) -> Result<(), RdmError> {
+                    out.extend(self.to_be_bytes());
+                    Ok(())
+                }
+
+                fn decode(data: &mut &[u8]) -> Result<Self, RdmError> {
+                    Ok(<$int>::from_be_bytes(take(data, Self::MIN_LEN)?.try_into().unwrap()))
+                }
+            }
+        )*
+    };
+}
+
+pd_field_int!(u8, u16, u32, u64, i8, i16, i32, i64);
+
+/// One byte, anything but zero is true
+impl PdField for bool {
+    const MIN_LEN : usize = 1;
+
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), RdmError> {
+        out.push(*self as u8);
+        Ok(())
+    }
+
+    fn decode(data: &mut &[u8]) -> Result<Self, RdmError> {
+        Ok(take(data, 1)?[0] != 0)
+    }
+}
+
+impl PdField for Uid {
+    const MIN_LEN : usize = 6;
+
+    fn encode(&self, out: &mut Vec<u8>) -> Result<(), RdmError> {
+        out.extend(self.uid_serialize());
+        Ok(())
+    }
+
+    fn decode(data: &mut &[u8]) -> Result<Self, RdmError> {
+        Uid::from_bytes(take(data, 6)?)
+    }
+}
+
+/// PdCodec::serialize for a derived struct
+pub fn serialize<T: PdField>(value: &T) -> Result<Vec<u8>, RdmError> {
+    let mut out = Vec::new();
+    value.encode(&mut out)?;
+    Ok(out)
+}
+
+/// PdCodec::deserialize for a derived struct.  Short data is reported against the whole
+/// parameter data rather than the field that ran out.
+pub fn deserialize<T: PdField>(data: &[u8]) -> Result<T, RdmError> {
+    if data.len() < T::MIN_LEN {
+        return Err(RdmError::TooShort { needed: T::MIN_LEN, got: data.len() });
+    }
+
+    T::decode(&mut &data[..])
+}
+
+/// Refuses len if it is past max_len
+fn check_len(len: usize, max_len: Option<usize>) -> Result<(), RdmError> {
+    match max_len {
+        Some(max_len) if len > max_len => Err(RdmError::FieldTooLong { len, max_len }),
+        _ => Ok(())
+    }
+}
+
+/// A string of exactly len bytes, padded with nulls
+pub fn encode_fixed_string(value: &str, len: usize, out: &mut Vec<u8>) -> Result<(), RdmError> {
+    check_len(value.len(), Some(len))?;
+    out.extend(value.as_bytes());
+    out.resize(out.len() + len - value.len(), 0x00);
+    Ok(())
+}
+
+pub fn decode_fixed_string(data: &mut &[u8], len: usize) -> Result<String, RdmError> {
+    Ok(pd_string(take(data, len)?))
+}
+
+/// A string that ends the parameter data, of no more than max_len bytes
+pub fn encode_string(value: &str, max_len: Option<usize>, out: &mut Vec<u8>) -> Result<(), RdmError> {
+    check_len(value.len(), max_len)?;
+    out.extend(value.as_bytes());
+    Ok(())
+}
+
+/// Reads the rest of data as a string, of which only the first max_len bytes count
+pub fn decode_string(data: &mut &[u8], max_len: Option<usize>) -> String {
+    let rest = std::mem::take(data);
+    let len = max_len.unwrap_or(rest.len()).min(rest.len());
+
+    pd_string(&rest[..len])
+}
+
+/// A repeated group that ends the parameter data, of no more than max_len entries
+pub fn encode_repeated<T: PdField>(values: &[T], max_len: Option<usize>, out: &mut Vec<u8>) -> Result<(), RdmError> {
+    check_len(values.len(), max_len)?;
+
+    for value in values {
+        value.encode(out)?;
+    }
+
+    Ok(())
+}
+
+/// Reads entries until data runs out.  A trailing partial entry is dropped; whole entries
+/// past max_len fail with FieldTooLong.
+pub fn decode_repeated<T: PdField>(data: &mut &[u8], max_len: Option<usize>) -> Result<Vec<T>, RdmError> {
+    let mut values = Vec::new();
+
+    while !data.is_empty() && data.len() >= T::MIN_LEN {
+        if let Some(max_len) = max_len.filter(|&max_len| values.len() == max_len) {
+            let extra = data.len().checked_div(T::MIN_LEN).unwrap_or(1);
+            return Err(RdmError::FieldTooLong { len: max_len + extra, max_len });
+        }
+
+        let before = data.len();
+        values.push(T::decode(data)?);
+
+        // An entry of nothing but variable length fields has already taken the rest
+        if data.len() == before {
+            break;
+        }
+    }
+
+    *data = &[];
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, Clone, PartialEq, RdmParameter)]
+    struct Slot {
+        offset: u16,
+        slot_type: u8,
+        label: u16
+    }
+
+    #[derive(Debug, Clone, PartialEq, RdmParameter)]
+    struct Range(i16, i16);
+
+    /// A manufacturer-specific PID with a bit of everything
+    #[derive(Debug, Clone, PartialEq, RdmParameter)]
+    #[rdm(pid = 0x8001, get)]
+    struct FixtureProfile {
+        enabled: bool,
+        owner: Uid,
+        #[rdm(len = 8)]
+        mode: String,
+        pan: Range,
+        #[rdm(max_len = 4)]
+        slots: Vec<Slot>
+    }
+
+    #[derive(Debug, Clone, PartialEq, RdmParameter)]
+    #[rdm(pid = 0x8002)]
+    struct Note {
+        priority: u8,
+        #[rdm(max_len = 32)]
+        text: String
+    }
+
+    #[test]
+    fn test_derive_round_trip() {
+        let profile = FixtureProfile {
+            enabled: true,
+            owner: Uid::new(0x044E, 0x00000001),
+            mode: "16bit".to_string(),
+            pan: Range(-270, 270),
+            slots: vec![Slot { offset: 0, slot_type: 0, label: 0x0101 }, Slot { offset: 1, slot_type: 1, label: 0x0101 }]
+        };
+
+        let pd = profile.serialize().unwrap();
+        assert_eq!(<FixtureProfile as PdField>::MIN_LEN, 1 + 6 + 8 + 4);
+        assert_eq!(pd.len(), 19 + 2 * 5);
+        assert_eq!(pd[0..7], [0x01, 0x04, 0x4E, 0x00, 0x00, 0x00, 0x01]);
+        assert_eq!(pd[7..15], *b"16bit\0\0\0");
+        assert_eq!(pd[15..19], [0xFE, 0xF2, 0x01, 0x0E]);
+        assert_eq!(FixtureProfile::deserialize(pd.clone()), Ok(profile.clone()));
+
+        // A partial trailing entry is dropped, but whole entries past max_len are refused
+        let mut partial = pd.clone();
+        partial.extend([0x00, 0x02]);
+        assert_eq!(FixtureProfile::deserialize(partial), Ok(profile.clone()));
+
+        let mut long = pd[..19].to_vec();
+        long.extend([0x00, 0x02, 0x00, 0x00, 0x00].repeat(4));
+        assert_eq!(FixtureProfile::deserialize(long.clone()).unwrap().slots.len(), 4);
+        long.extend([0x00, 0x02, 0x00, 0x00, 0x00].repeat(2));
+        assert_eq!(FixtureProfile::deserialize(long), Err(RdmError::FieldTooLong { len: 6, max_len: 4 }));
+
+        let mut too_many = profile.clone();
+        too_many.slots = vec![Slot { offset: 2, slot_type: 0, label: 0 }; 6];
+        assert_eq!(too_many.serialize(), Err(RdmError::FieldTooLong { len: 6, max_len: 4 }));
+
+        let mut long_mode = profile.clone();
+        long_mode.mode = "16bit fine".to_string();
+        assert_eq!(long_mode.serialize(), Err(RdmError::FieldTooLong { len: 10, max_len: 8 }));
+
+        assert_eq!(FixtureProfile::deserialize(pd[..18].to_vec()), Err(RdmError::TooShort { needed: 19, got: 18 }));
+
+        // Only a struct that ends in a variable length field has to come last in another
+        const _ : () = assert!(<FixtureProfile as PdField>::TRAILING && <Note as PdField>::TRAILING);
+        const _ : () = assert!(!<Range as PdField>::TRAILING && !<Slot as PdField>::TRAILING && !<Uid as PdField>::TRAILING);
+
+        assert_eq!(FixtureProfile::PID, 0x8001);
+        assert_eq!(FixtureProfile::COMMAND_CLASS, ParameterCommandClass::Get);
+    }
+
+    #[test]
+    fn test_derive_strings() {
+        // max_len is refused on the way out, in bytes rather than characters, and the rest of
+        // the data is ignored on the way in
+        let note = Note { priority: 2, text: "x".repeat(32) };
+        assert_eq!(note.serialize().unwrap().len(), 33);
+        assert_eq!(Note { priority: 2, text: "x".repeat(40) }.serialize(), Err(RdmError::FieldTooLong { len: 40, max_len: 32 }));
+
+        let accented = Note { priority: 0, text: format!("{}é", "a".repeat(31)) };
+        assert_eq!(accented.serialize(), Err(RdmError::FieldTooLong { len: 33, max_len: 32 }));
+
+        let mut long = vec![0x01];
+        long.extend([b'y'; 40]);
+        assert_eq!(Note::deserialize(long).unwrap().text.len(), 32);
+
+        // A sender's terminator ends the string
+        assert_eq!(Note::deserialize(b"\x00Spot\x00junk".to_vec()).unwrap().text, "Spot");
+
+        assert_eq!(Note::COMMAND_CLASS, ParameterCommandClass::GetSet);
+        let set = RdmRequest::set(Uid::new(1, 1), Note::PID).pd(&note.serialize().unwrap()).build().unwrap();
+        assert!(matches!(ParameterRequest::<Note>::decode(&set), Ok(ParameterRequest::Set(_))));
+    }
+}
@@ -0,0 +1,497 @@
+//! A simulated DMX line of virtual responders, for testing controllers without hardware
+//! The line implements both transport traits.  Time on the line is virtual: every request
+//! advances a clock by the line's latency, or by its timeout when nothing answers, and sleep
+//! advances it without blocking, so large rigs run at full speed.
+
+use std::collections::BTreeMap;
+use std::future::{self, Future};
+use std::time::Duration;
+
+use crate::*;
+
+/// How long a controller waits for a response before treating the line as quiet
+pub const RESPONSE_TIMEOUT : Duration = Duration::from_micros(2800);
+
+/// How a virtual responder departs from the standard
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Profile {
+    #[default]
+    Compliant,
+    /// Answers DISC_UNIQUE_BRANCH but never acknowledges DISC_MUTE
+    IgnoresMute,
+    /// Never answers DISC_UNIQUE_BRANCH, so can only be reached by UID
+    NoDiscovery,
+    /// Sends this many preamble bytes, 0 to 7, ahead of its DUB response
+    ShortPreamble(u8),
+    /// Answers with the TN after the one it was sent
+    WrongTn,
+    /// Answers with a bad checksum
+    BadChecksum,
+    /// Takes this long to answer, on top of the line's latency
+    Slow(Duration),
+    /// Never answers anything
+    Dead
+}
+
+/// One device on the line
+#[derive(Debug, Clone)]
+pub struct Responder {
+    pub uid: Uid,
+    pub profile: Profile,
+    pub mute_response: MuteResponse,
+    pub muted: bool,
+    parameters: BTreeMap<u16, Vec<u8>>,
+    /// PID and bytes already sent of an ACK_OVERFLOW in progress
+    overflow: Option<(u16, usize)>
+}
+
+impl Responder {
+    /// A compliant, un-muted responder that supports DEVICE_INFO
+    pub fn new(uid: Uid) -> Responder {
+        let mut device_info = DeviceInfoPD::new();
+        device_info.rdm_protocol_version = 0x0100;
+
+        Responder {
+            uid,
+            profile: Profile::Compliant,
+            mute_response: MuteResponse::default(),
+            muted: false,
+            parameters: BTreeMap::from([(DEVICE_INFO, device_info.serialize().expect("DEVICE_INFO is fixed width"))]),
+            overflow: None
+        }
+    }
+
+    pub fn profile(mut self, profile: Profile) -> Responder {
+        self.profile = profile;
+        self
+    }
+
+    pub fn mute_response(mut self, mute_response: MuteResponse) -> Responder {
+        self.mute_response = mute_response;
+        self
+    }
+
+    /// Supports pid, answering GET with pd and storing whatever is SET.
+    /// pd longer than RDM_MAX_PDL is sent as ACK_OVERFLOW.
+    pub fn parameter(mut self, pid: u16, pd: &[u8]) -> Responder {
+        self.parameters.insert(pid, pd.to_vec());
+        self
+    }
+
+    /// Current value of pid, if the responder supports it
+    pub fn get(&self, pid: u16) -> Option<&[u8]> {
+        self.parameters.get(&pid).map(|pd| pd.as_slice())
+    }
+
+    fn answers_dub(&self, range: &UidRange) -> bool {
+        !self.muted
+            && !matches!(self.profile, Profile::NoDiscovery | Profile::Dead)
+            && range.contains(&self.uid)
+    }
+
+    fn dub_response(&self) -> Vec<u8> {
+        let encoded = DiscUniqueBranchResponse::encode(&self.uid);
+
+        match self.profile {
+            Profile::ShortPreamble(preamble) => encoded[DUB_MAX_PREAMBLE - (preamble as usize).min(DUB_MAX_PREAMBLE)..].to_vec(),
+            _ => encoded.to_vec()
+        }
+    }
+
+    /// Acts on request and returns the frame to send back, if any
+    fn handle(&mut self, request: &Pkt) -> Option<Vec<u8>> {
+        if self.profile == Profile::Dead {
+            return None;
+        }
+
+        let response = match (request.cc, request.pid) {
+            (DISCOVERY_COMMAND, DISC_MUTE) if self.profile == Profile::IgnoresMute => return None,
+            (DISCOVERY_COMMAND, DISC_MUTE) => {
+                self.muted = true;
+                Pkt::ack(request, &self.mute_response.serialize(), 0)
+            },
+            (DISCOVERY_COMMAND, DISC_UN_MUTE) => {
+                self.muted = false;
+                Pkt::ack(request, &self.mute_response.serialize(), 0)
+            },
+            (GET_COMMAND | SET_COMMAND, _) if request.subdevice != 0 => Pkt::nack(request, NackReason::SubDeviceOutOfRange, 0),
+            (GET_COMMAND, pid) => match self.parameters.get(&pid) {
+                None => Pkt::nack(request, NackReason::UnknownPid, 0),
+                Some(pd) => {
+                    let sent = match self.overflow {
+                        Some((overflow_pid, sent)) if overflow_pid == pid => sent,
+                        _ => 0
+                    };
+                    let end = pd.len().min(sent + RDM_MAX_PDL);
+
+                    if end < pd.len() {
+                        self.overflow = Some((pid, end));
+                        Pkt::ack_overflow(request, &pd[sent..end], 0)
+                    } else {
+                        self.overflow = None;
+                        Pkt::ack(request, &pd[sent..end], 0)
+                    }
+                }
+            },
+            (SET_COMMAND, pid) => match self.parameters.get_mut(&pid) {
+                None => Pkt::nack(request, NackReason::UnknownPid, 0),
+                Some(pd) => {
+                    *pd = request.pd.clone();
+                    Pkt::ack(request, &[], 0)
+                }
+            },
+            _ => return None
+        };
+
+        let mut response = response.ok()?;
+
+        if self.profile == Profile::WrongTn {
+            response.tn = response.tn.wrapping_add(1);
+            response.set_checksum();
+        }
+
+        let mut data = response.serialize();
+
+        if self.profile == Profile::BadChecksum {
+            let last = data.len() - 1;
+            data[last] ^= 0xFF;
+        }
+
+        Some(data)
+    }
+
+    /// Delay before this responder's answer starts
+    fn delay(&self) -> Duration {
+        match self.profile {
+            Profile::Slow(delay) => delay,
+            _ => Duration::ZERO
+        }
+    }
+}
+
+/// A line of responders
+/// Devices answering at once are wired-AND together, as on a real line, so a DUB with more
+/// than one device in range comes back garbled and two responders sharing a UID collide on
+/// every frame.
+pub struct Line {
+    responders: Vec<Responder>,
+    /// Chance, 0.0 to 1.0, that a response is lost
+    pub loss: f64,
+    /// Chance, 0.0 to 1.0, that a response has a bit flipped
+    pub corruption: f64,
+    /// Time from the end of a request to the start of the response
+    pub latency: Duration,
+    /// Responses that start later than this are not heard
+    pub timeout: Duration,
+    rng: u64,
+    clock: Duration,
+    requests: usize
+}
+
+impl Default for Line {
+    fn default() -> Self {
+        Line::new()
+    }
+}
+
+impl Line {
+    /// An empty, lossless line
+    pub fn new() -> Line {
+        Line {
+            responders: Vec::new(),
+            loss: 0.0,
+            corruption: 0.0,
+            latency: Duration::ZERO,
+            timeout: RESPONSE_TIMEOUT,
+            rng: 0x2545_F491_4F6C_DD1D,
+            clock: Duration::ZERO,
+            requests: 0
+        }
+    }
+
+    /// count compliant responders with distinct random UIDs.
+    /// The same seed always gives the same rig, and seeds the loss and corruption rolls.
+    pub fn random(count: usize, seed: u64) -> Line {
+        let mut line = Line::new().seed(seed);
+        let mut uids : Vec<Uid> = Vec::new();
+
+        while uids.len() < count {
+            let value = line.next_random();
+            let uid = Uid::new((value >> 32) as u16 % 0x7FFF + 1, value as u32);
+
+            if !uids.contains(&uid) {
+                uids.push(uid);
+            }
+        }
+
+        line.responders = uids.into_iter().map(Responder::new).collect();
+        line
+    }
+
+    /// Reseeds the loss and corruption rolls
+    pub fn seed(mut self, seed: u64) -> Line {
+        // xorshift gets stuck on 0
+        self.rng = seed | 1;
+        self
+    }
+
+    pub fn add(&mut self, responder: Responder) {
+        self.responders.push(responder);
+    }
+
+    pub fn responders(&self) -> &[Responder] {
+        &self.responders
+    }
+
+    pub fn responder(&self, uid: &Uid) -> Option<&Responder> {
+        self.responders.iter().find(|responder| responder.uid == *uid)
+    }
+
+    pub fn responder_mut(&mut self, uid: &Uid) -> Option<&mut Responder> {
+        self.responders.iter_mut().find(|responder| responder.uid == *uid)
+    }
+
+    /// The UIDs that discovery should find: everything that answers DUB
+    pub fn discoverable(&self) -> Vec<Uid> {
+        let mut uids : Vec<Uid> = self.responders.iter()
+            .filter(|responder| !matches!(responder.profile, Profile::NoDiscovery | Profile::Dead))
+            .map(|responder| responder.uid)
+            .collect();
+        uids.sort();
+        uids.dedup();
+        uids
+    }
+
+    /// Virtual time spent on the line so far
+    pub fn now(&self) -> Duration {
+        self.clock
+    }
+
+    /// Frames sent to the line so far
+    pub fn requests(&self) -> usize {
+        self.requests
+    }
+
+    /// Sends data to every responder and returns what the controller would hear
+    pub fn transact(&mut self, data: &[u8]) -> Option<Vec<u8>> {
+        self.requests += 1;
+
+        let request = match Pkt::deserialize(data.to_vec()) {
+            Ok(request) => request,
+            Err(e) => {
+                debug!("sim: ignoring bad frame: {}", e);
+                self.clock += self.timeout;
+                return None;
+            }
+        };
+
+        let mut answers : Vec<(Duration, Vec<u8>)> = Vec::new();
+
+        let dub_range = match (request.cc, request.pid) {
+            (DISCOVERY_COMMAND, DISC_UNIQUE_BRANCH) => UidRange::from_bytes(&request.pd).ok(),
+            _ => None
+        };
+
+        if let Some(range) = dub_range {
+
+            for responder in self.responders.iter().filter(|responder| responder.answers_dub(&range)) {
+                answers.push((responder.delay(), responder.dub_response()));
+            }
+        } else {
+            let broadcast = request.destination.is_broadcast() || request.destination.is_vendorcast();
+
+            for responder in self.responders.iter_mut().filter(|responder| request.destination.addresses(&responder.uid)) {
+                let delay = responder.delay();
+
+                // Broadcasts are acted on but never answered
+                if let Some(response) = responder.handle(&request) {
+                    if !broadcast {
+                        answers.push((delay, response));
+                    }
+                }
+            }
+        }
+
+        let latency = self.latency;
+        let timeout = self.timeout;
+        answers.retain(|(delay, _)| latency + *delay <= timeout);
+
+        let mut response = match answers.iter().map(|(_, data)| data.clone()).reduce(wired_and) {
+            None => {
+                self.clock += timeout;
+                return None;
+            },
+            Some(response) => response
+        };
+
+        let slowest = answers.iter().map(|(delay, _)| *delay).max().unwrap_or_default();
+        self.clock += latency + slowest;
+
+        if self.roll(self.loss) {
+            debug!("sim: lost response to {}", request.parameter_id());
+            return None;
+        }
+
+        if self.roll(self.corruption) {
+            let bit = self.next_random() as usize % (response.len() * 8);
+            response[bit / 8] ^= 1 << (bit % 8);
+        }
+
+        Some(response)
+    }
+
+    /// Sends data without listening for a response, so the clock does not run to the timeout
+    fn broadcast(&mut self, data: &[u8]) {
+        let clock = self.clock;
+        self.transact(data);
+        self.clock = clock;
+    }
+
+    fn next_random(&mut self) -> u64 {
+        self.rng ^= self.rng << 13;
+        self.rng ^= self.rng >> 7;
+        self.rng ^= self.rng << 17;
+        self.rng
+    }
+
+    fn roll(&mut self, chance: f64) -> bool {
+        chance > 0.0 && ((self.next_random() >> 11) as f64 / (1u64 << 53) as f64) < chance
+    }
+}
+
+/// Two transmitters on one line: a bit is only high if both send it high.
+/// The shorter frame ends first and the idle line is high, so the longer one comes through.
+fn wired_and(a: Vec<u8>, b: Vec<u8>) -> Vec<u8> {
+    let (mut long, short) = if a.len() >= b.len() { (a, b) } else { (b, a) };
+
+    for (byte, other) in long.iter_mut().zip(short.iter()) {
+        *byte &= other;
+    }
+
+    long
+}
+
+impl RdmTransport for Line {
+    fn send_request(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>, TransportError> {
+        Ok(self.transact(data))
+    }
+
+    fn send_broadcast(&mut self, data: &[u8]) -> Result<(), TransportError> {
+        self.broadcast(data);
+        Ok(())
+    }
+}
+
+impl AsyncRdmTransport for Line {
+    fn send_request(&mut self, data: &[u8]) -> impl Future<Output = Result<Option<Vec<u8>>, TransportError>> {
+        future::ready(Ok(self.transact(data)))
+    }
+
+    fn send_broadcast(&mut self, data: &[u8]) -> impl Future<Output = Result<(), TransportError>> {
+        self.broadcast(data);
+        future::ready(Ok(()))
+    }
+
+    /// Advances the line's clock rather than waiting
+    fn sleep(&mut self, duration: Duration) -> impl Future<Output = ()> {
+        self.clock += duration;
+        future::ready(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONTROLLER : Uid = Uid { mfg: 0x044E, dev: 0x00000001 };
+
+    #[test]
+    fn test_sim_discovers_large_rig() {
+        let mut line = Line::random(2000, 7);
+        let expected = line.discoverable();
+        assert_eq!(expected.len(), 2000);
+
+        let found = block_on(do_discovery_algo_async(&mut line, &CONTROLLER, true, false)).unwrap();
+        assert_eq!(found, expected);
+        assert!(line.responders().iter().all(|responder| responder.muted));
+        assert!(line.now() > Duration::ZERO);
+    }
+
+    #[test]
+    fn test_sim_misbehaving_responders() {
+        let mut line = Line::new();
+        line.add(Responder::new(Uid::new(0x3638, 1)).profile(Profile::ShortPreamble(0)));
+        line.add(Responder::new(Uid::new(0x3638, 2)).profile(Profile::NoDiscovery));
+        line.add(Responder::new(Uid::new(0x3638, 3)).profile(Profile::Slow(Duration::from_millis(5))));
+        line.add(Responder::new(Uid::new(0x3638, 4)).profile(Profile::Slow(Duration::from_millis(1))));
+        line.add(Responder::new(Uid::new(0x3638, 5)).profile(Profile::Dead));
+
+        let found = do_discovery_algo(&mut line, &CONTROLLER, true, false).unwrap();
+        assert_eq!(found, vec![Uid::new(0x3638, 1), Uid::new(0x3638, 4)]);
+
+        // Never muting ends in an unresolvable collision rather than a hang
+        let mut line = Line::new();
+        line.add(Responder::new(Uid::new(0x3638, 1)));
+        line.add(Responder::new(Uid::new(0x3638, 2)).profile(Profile::IgnoresMute));
+        assert_eq!(do_discovery_algo(&mut line, &CONTROLLER, true, false), Err(RdmError::UnresolvableCollision(Uid::new(0x3638, 2))));
+
+        // Two responders sharing a UID send identical bits, so the wired-AND hides them
+        let mut line = Line::new();
+        line.add(Responder::new(Uid::new(0x3638, 1)));
+        line.add(Responder::new(Uid::new(0x3638, 1)));
+        assert_eq!(do_discovery_algo(&mut line, &CONTROLLER, true, false), Ok(vec![Uid::new(0x3638, 1)]));
+
+        // Unless their timing differs
+        let mut line = Line::new();
+        line.add(Responder::new(Uid::new(0x3638, 1)));
+        line.add(Responder::new(Uid::new(0x3638, 1)).profile(Profile::ShortPreamble(3)));
+        assert_eq!(do_discovery_algo(&mut line, &CONTROLLER, true, false), Err(RdmError::UnresolvableCollision(Uid::new(0x3638, 1))));
+    }
+
+    #[test]
+    fn test_sim_loss_and_corruption() {
+        let mut line = Line::random(50, 11);
+        // An empty looking branch only gets one more probe, so two losses in a row on a branch
+        // with devices in it hides them
+        line.loss = 0.02;
+        line.corruption = 0.05;
+        let expected = line.discoverable();
+
+        let config = DiscoveryConfig { retry_empty_branches: true, mute_retries: 3, ..DiscoveryConfig::default() };
+        let found = do_discovery_configured(&mut line, &CONTROLLER, &config, &mut ()).unwrap().tod();
+        assert_eq!(found, expected);
+    }
+
+    #[test]
+    fn test_sim_get_and_set() {
+        let uid = Uid::new(0x3638, 1);
+        let mut line = Line::new();
+        line.add(Responder::new(uid)
+            .parameter(DEVICE_LABEL, b"Stage left")
+            .parameter(SLOT_INFO, &[0x5A; 500]));
+        line.add(Responder::new(Uid::new(0x3638, 2)).profile(Profile::WrongTn));
+        line.add(Responder::new(Uid::new(0x3638, 3)).profile(Profile::BadChecksum));
+
+        let mut tn = 0;
+        let info = DeviceInfoPD::deserialize(do_get(&mut line, &CONTROLLER, &uid, 0, DEVICE_INFO, &[], &mut tn, &mut Vec::new()).unwrap()).unwrap();
+        assert_eq!(info.rdm_protocol_version, 0x0100);
+
+        // Longer than one frame, so it comes back in ACK_OVERFLOW chunks
+        assert_eq!(do_get(&mut line, &CONTROLLER, &uid, 0, SLOT_INFO, &[], &mut tn, &mut Vec::new()), Ok(vec![0x5A; 500]));
+
+        do_set(&mut line, &CONTROLLER, &uid, 0, DEVICE_LABEL, b"Stage right", &mut tn, &mut Vec::new()).unwrap();
+        assert_eq!(line.responder(&uid).unwrap().get(DEVICE_LABEL), Some(&b"Stage right"[..]));
+
+        assert_eq!(do_get(&mut line, &CONTROLLER, &uid, 0, SENSOR_VALUE, &[], &mut tn, &mut Vec::new()), Err(RdmError::Nack(NackReason::UnknownPid)));
+        assert_eq!(do_get(&mut line, &CONTROLLER, &uid, 1, DEVICE_INFO, &[], &mut tn, &mut Vec::new()), Err(RdmError::Nack(NackReason::SubDeviceOutOfRange)));
+        assert!(do_get(&mut line, &CONTROLLER, &Uid::new(0x3638, 2), 0, DEVICE_INFO, &[], &mut tn, &mut Vec::new()).is_err());
+        assert!(do_get(&mut line, &CONTROLLER, &Uid::new(0x3638, 3), 0, DEVICE_INFO, &[], &mut tn, &mut Vec::new()).is_err());
+
+        // Vendorcast SET reaches every device of the manufacturer and nobody answers
+        let before = line.now();
+        let request = RdmRequest::set(Uid::vendorcast(0x3638), DEVICE_LABEL).source(CONTROLLER).pd(b"All").build().unwrap();
+        assert_eq!(line.transact(&request.serialize()), None);
+        assert_eq!(line.responder(&uid).unwrap().get(DEVICE_LABEL), Some(&b"All"[..]));
+        assert_eq!(line.now() - before, RESPONSE_TIMEOUT);
+    }
+}
@@ -0,0 +1,139 @@
+//! The link between the library and whatever actually puts bytes on the wire
+//! A transport owns its port, socket or test state; the library only ever borrows it.
+
+use std::future::{self, Future};
+use std::io;
+use std::pin::pin;
+use std::sync::Arc;
+use std::task::{Context, Poll, Wake, Waker};
+use std::thread;
+use std::time::Duration;
+
+/// A failure of the link itself, as opposed to a device that did not answer
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransportError {
+    pub kind: io::ErrorKind,
+    pub message: String
+}
+
+impl TransportError {
+    pub fn new(kind: io::ErrorKind, message: &str) -> TransportError {
+        TransportError { kind, message: message.to_string() }
+    }
+}
+
+impl From<io::Error> for TransportError {
+    fn from(e: io::Error) -> Self {
+        TransportError { kind: e.kind(), message: e.to_string() }
+    }
+}
+
+impl std::fmt::Display for TransportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "{:?}: {}", self.kind, self.message)
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+/// Sends RDM frames and collects what comes back
+/// Ok(None) from send_request means the line stayed quiet until the transport's timeout; Err is
+/// kept for the link failing, so callers can tell an absent device from a broken port.
+///
+/// Any FnMut(&[u8]) -> Option<Vec<u8>> is a transport that never fails, which is what the
+/// discovery functions used to take as a function pointer.
+pub trait RdmTransport {
+    /// Sends a frame and waits for the response
+    /// DISC_UNIQUE_BRANCH responses have no RDM framing, so the data is handed back raw.
+    fn send_request(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>, TransportError>;
+
+    /// Sends a frame that no device will answer, without waiting for a response
+    fn send_broadcast(&mut self, data: &[u8]) -> Result<(), TransportError> {
+        self.send_request(data).map(|_| ())
+    }
+}
+
+impl<F> RdmTransport for F where F: FnMut(&[u8]) -> Option<Vec<u8>> {
+    fn send_request(&mut self, data: &[u8]) -> Result<Option<Vec<u8>>, TransportError> {
+        Ok(self(data))
+    }
+}
+
+/// Async counterpart of RdmTransport
+/// Nothing here ties the library to an executor: the transport brings its own I/O and its own
+/// timer through sleep, which is used to wait out ACK_TIMER estimates.  Dropping any of the
+/// async functions' futures cancels the operation between frames.
+pub trait AsyncRdmTransport {
+    /// Sends a frame and waits for the response, Ok(None) if the line stays quiet
+    fn send_request(&mut self, data: &[u8]) -> impl Future<Output = Result<Option<Vec<u8>>, TransportError>>;
+
+    /// Sends a frame that no device will answer, without waiting for a response
+    fn send_broadcast(&mut self, data: &[u8]) -> impl Future<Output = Result<(), TransportError>>;
+
+    /// Waits for duration using the executor's timer
+    fn sleep(&mut self, duration: Duration) -> impl Future<Output = ()>;
+}
+
+/// Runs a blocking RdmTransport behind the async interface.
+/// Every future it returns is already complete, which is what lets the synchronous API be a
+/// block_on over the async one.
+pub struct BlockingTransport<'a, T: RdmTransport + ?Sized>(pub &'a mut T);
+
+impl<T: RdmTransport + ?Sized> AsyncRdmTransport for BlockingTransport<'_, T> {
+    fn send_request(&mut self, data: &[u8]) -> impl Future<Output = Result<Option<Vec<u8>>, TransportError>> {
+        future::ready(self.0.send_request(data))
+    }
+
+    fn send_broadcast(&mut self, data: &[u8]) -> impl Future<Output = Result<(), TransportError>> {
+        future::ready(self.0.send_broadcast(data))
+    }
+
+    fn sleep(&mut self, duration: Duration) -> impl Future<Output = ()> {
+        thread::sleep(duration);
+        future::ready(())
+    }
+}
+
+struct ThreadWaker(thread::Thread);
+
+impl Wake for ThreadWaker {
+    fn wake(self: Arc<Self>) {
+        self.0.unpark();
+    }
+}
+
+/// Drives a future to completion on the current thread
+pub fn block_on<F: Future>(fut: F) -> F::Output {
+    let mut fut = pin!(fut);
+    let waker = Waker::from(Arc::new(ThreadWaker(thread::current())));
+    let mut cx = Context::from_waker(&waker);
+
+    loop {
+        if let Poll::Ready(output) = fut.as_mut().poll(&mut cx) {
+            return output;
+        }
+        thread::park();
+    }
+}
+
+/// Output of timeout: Err(Elapsed) if the deadline won
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Elapsed;
+
+/// Races fut against deadline, any future that completes when time is up, e.g.
+/// timeout(tokio::time::sleep(Duration::from_secs(2)), do_get_async(..)).  The loser is dropped,
+/// which cancels it.
+pub async fn timeout<D: Future<Output = ()>, F: Future>(deadline: D, fut: F) -> Result<F::Output, Elapsed> {
+    let mut deadline = pin!(deadline);
+    let mut fut = pin!(fut);
+
+    future::poll_fn(|cx| {
+        if let Poll::Ready(output) = fut.as_mut().poll(cx) {
+            return Poll::Ready(Ok(output));
+        }
+        if deadline.as_mut().poll(cx).is_ready() {
+            return Poll::Ready(Err(Elapsed));
+        }
+        Poll::Pending
+    }).await
+}